@@ -0,0 +1,947 @@
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::error_code::ErrorCode;
+use crate::lexer::{Literal, Span};
+use crate::pair::Pair;
+use crate::parser::{Arm, BinaryOperator, Method, Type, UnaryOperator, Visibility, AST};
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+
+// integer and float literals without a suffix fit any integer or float type
+const INTEGER_TYPES: [Symbol; 10] = [
+    Symbol::INT, Symbol::INT8, Symbol::INT16, Symbol::INT32, Symbol::INT64,
+    Symbol::UINT8, Symbol::UINT16, Symbol::UINT32, Symbol::UINT64, Symbol::INTEGER
+];
+const FLOAT_TYPES: [Symbol; 3] = [Symbol::FLOAT32, Symbol::FLOAT64, Symbol::FLOAT];
+
+#[derive(Debug, Clone)]
+struct Variable {
+    mutable: bool,
+    // where it was declared
+    span: Span,
+    // None when it couldn't be inferred
+    variable_type: Option<Type>,
+}
+
+// a field or method as seen from outside the struct
+#[derive(Debug, Clone)]
+struct Member {
+    visibility: Visibility,
+    // the field's type or the method's function type without `self`
+    member_type: Type,
+    is_method: bool,
+    span: Span,
+}
+
+#[derive(Debug, Default)]
+struct StructInfo {
+    members: HashMap<Symbol, Member>,
+    // in declaration order, for listing missing fields
+    fields: Vec<Symbol>,
+    constructor: Option<Member>,
+}
+
+// the struct the object of every field access and method call resolved to,
+// keyed by the span of the access
+pub type Receivers = HashMap<Span, Symbol>;
+
+// checks a parsed file for errors the grammar can't express. A variable may
+// shadow one from an enclosing block but not one declared in the same block.
+//
+// Types are only inferred as far as needed to compare the branches of
+// value-producing ifs and declared variable types, anything unknown is None
+// and never reported.
+pub struct Checker {
+    // innermost block last, arguments live in a scope of their own
+    scopes: Vec<HashMap<Symbol, Variable>>,
+    // type of every function in the file
+    functions: HashMap<Symbol, Type>,
+    // labels of the loops around the current statement, innermost last
+    loops: Vec<Option<Symbol>>,
+    structs: HashMap<Symbol, StructInfo>,
+    // the struct whose methods are being checked, its non-public members are visible
+    current_struct: Option<Symbol>,
+    receivers: Receivers,
+    diagnostics: Diagnostics,
+}
+
+impl Checker {
+    pub fn check(ast: &AST) -> Diagnostics {
+        Self::resolve(ast).0
+    }
+
+    // checks `ast` and also returns the structs codegen needs to lay out
+    // field accesses and method calls
+    pub fn resolve(ast: &AST) -> (Diagnostics, Receivers) {
+        let mut checker = Checker {
+            scopes: Vec::new(),
+            functions: HashMap::new(),
+            loops: Vec::new(),
+            structs: HashMap::new(),
+            current_struct: None,
+            receivers: Receivers::new(),
+            diagnostics: Diagnostics::new()
+        };
+
+        if let AST::File { child, .. } = ast {
+            for node in child {
+                match node {
+                    AST::FunctionDefinition { name, args, return_type, .. } => {
+                        checker.functions.insert(*name, Type::Function {
+                            params: args.iter().map(|arg| arg.1.clone()).collect(),
+                            return_type: Box::new(return_type.clone())
+                        });
+                    }
+                    AST::StructDefinition { name, .. } => {
+                        let info = checker.struct_info(node);
+                        checker.structs.insert(*name, info);
+                    }
+                    _ => {}
+                }
+            }
+            for node in child {
+                checker.item(node);
+            }
+        }
+
+        checker.diagnostics.sort();
+        (checker.diagnostics, checker.receivers)
+    }
+
+    fn item(&mut self, node: &AST) {
+        match node {
+            AST::FunctionDefinition { .. } => self.function(node, None),
+            AST::StructDefinition { name, constructors, methods, .. } => {
+                self.current_struct = Some(*name);
+                for method in constructors.iter().chain(methods) {
+                    self.function(&method.function, Some(*name));
+                }
+                self.current_struct = None;
+            }
+            _ => {}
+        }
+    }
+
+    // methods of `structure` also see `self`
+    fn function(&mut self, node: &AST, structure: Option<Symbol>) {
+        if let AST::FunctionDefinition { args, body, .. } = node {
+            let mut arguments = args.iter().map(|arg| {
+                (arg.0, Variable { mutable: false, span: Span::default(), variable_type: Some(arg.1.clone()) })
+            }).collect::<HashMap<_, _>>();
+            if let Some(structure) = structure {
+                arguments.insert(Symbol::SELF, Variable { mutable: false, span: Span::default(), variable_type: Some(Type::Named(structure)) });
+            }
+            self.scopes.push(arguments);
+            self.block(body, None);
+            self.scopes.pop();
+        }
+    }
+
+    // the members of a struct definition, reporting the ones declared twice
+    fn struct_info(&mut self, node: &AST) -> StructInfo {
+        let mut info = StructInfo::default();
+        let AST::StructDefinition { name, fields, constructors, methods, .. } = node else { return info };
+
+        let fields = fields.iter().map(|field| (field.name, Member {
+            visibility: field.visibility,
+            member_type: field.field_type.clone(),
+            is_method: false,
+            span: field.span
+        }));
+        let methods = methods.iter().filter_map(Self::method_member);
+        for (member, declaration) in fields.chain(methods) {
+            if let Some(previous) = info.members.get(&member) {
+                self.diagnostics.push(Diagnostic::error(ErrorCode::DuplicateMember, format!("'{}' is declared twice in struct '{}'", member, name), declaration.span)
+                    .with_label(previous.span, "previous declaration"));
+                continue;
+            }
+            if !declaration.is_method {
+                info.fields.push(member);
+            }
+            info.members.insert(member, declaration);
+        }
+
+        for constructor in constructors {
+            if let Some(previous) = &info.constructor {
+                self.diagnostics.push(Diagnostic::error(ErrorCode::DuplicateMember, format!("Struct '{}' has more than one constructor", name), constructor.span)
+                    .with_label(previous.span, "first constructor"));
+                continue;
+            }
+            info.constructor = Self::method_member(constructor).map(|(_, member)| member);
+        }
+
+        info
+    }
+
+    fn method_member(method: &Method) -> Option<(Symbol, Member)> {
+        let AST::FunctionDefinition { name, args, return_type, .. } = &method.function else { return None };
+        Some((*name, Member {
+            visibility: method.visibility,
+            member_type: Type::Function {
+                params: args.iter().map(|arg| arg.1.clone()).collect(),
+                return_type: Box::new(return_type.clone())
+            },
+            is_method: true,
+            span: method.span
+        }))
+    }
+
+    // looks up `name` on a value of `object_type`, reporting unknown and hidden
+    // members. Codegen needs the struct of every access, so an access whose
+    // struct isn't known never passes
+    fn member(&mut self, object_type: Option<Type>, name: Symbol, span: Span) -> Option<Member> {
+        let Some(object_type) = object_type else {
+            // usually the error that left the type unknown is reported already
+            if !self.diagnostics.has_errors() {
+                self.diagnostics.push(Diagnostic::error(ErrorCode::UnknownMember, format!("No field or method '{}' on a value of unknown type", name), span)
+                    .with_note("the type of the value before the '.' could not be inferred"));
+            }
+            return None;
+        };
+        let Some((structure, info)) = object_type.name().and_then(|structure| Some((structure, self.structs.get(&structure)?))) else {
+            self.diagnostics.push(Diagnostic::error(ErrorCode::UnknownMember, format!("No field or method '{}' on type '{}'", name, object_type), span)
+                .with_note("only structs have fields and methods"));
+            return None;
+        };
+        self.receivers.insert(span, structure);
+        let Some(member) = info.members.get(&name).cloned() else {
+            self.diagnostics.push(Diagnostic::error(ErrorCode::UnknownMember, format!("No field or method '{}' on struct '{}'", name, object_type), span));
+            return None;
+        };
+
+        self.visible(&member, format!("{} '{}' of struct '{}'", if member.is_method { "Method" } else { "Field" }, name, object_type), structure, span);
+        Some(member)
+    }
+
+    // reports use of a non-public member outside the struct's own methods
+    fn visible(&mut self, member: &Member, what: String, structure: Symbol, span: Span) {
+        if member.visibility == Visibility::Public || self.current_struct == Some(structure) {
+            return;
+        }
+
+        self.diagnostics.push(Diagnostic::error(ErrorCode::PrivateMember, format!("{} is {}", what, member.visibility.as_str()), span)
+            .with_label(member.span, format!("declared {} here", member.visibility.as_str()))
+            .with_help("mark it 'pub' to use it outside the struct"));
+    }
+
+    // name { field: value, ... }
+    fn struct_literal(&mut self, name: Symbol, values: &[Pair<Symbol, AST>], span: Span) -> Option<Type> {
+        let value_types = values.iter().map(|value| self.expression(&value.1)).collect::<Vec<_>>();
+        let Some(info) = self.structs.get(&name) else {
+            self.diagnostics.push(Diagnostic::error(ErrorCode::UnknownStruct, format!("Cannot find struct '{}'", name), span));
+            return None;
+        };
+
+        let missing = info.fields.iter().filter(|field| !values.iter().any(|value| value.0 == **field)).map(|field| format!("'{}'", field)).collect::<Vec<_>>();
+        // pushed once `info` is no longer borrowed
+        let mut diagnostics = Diagnostics::new();
+        let mut hidden = Vec::new();
+        for (value, value_type) in values.iter().zip(value_types) {
+            let field = info.members.get(&value.0).filter(|member| !member.is_method);
+            let Some(field) = field else {
+                diagnostics.push(Diagnostic::error(ErrorCode::UnknownMember, format!("Struct '{}' has no field '{}'", name, value.0), value.1.span()));
+                continue;
+            };
+            hidden.push((field.clone(), value.0, value.1.span()));
+
+            if let Some(value_type) = value_type.filter(|value_type| Self::unify(&field.member_type, value_type).is_none()) {
+                diagnostics.push(Diagnostic::error(ErrorCode::MismatchedTypes, "Mismatched types", value.1.span())
+                    .with_primary_label(format!("expected '{}', found '{}'", field.member_type, value_type))
+                    .with_label(field.span, format!("'{}' is declared as '{}' here", value.0, field.member_type)));
+            }
+        }
+
+        if !missing.is_empty() {
+            diagnostics.push(Diagnostic::error(ErrorCode::MissingFields, format!("Missing {} {} in '{}' literal", if missing.len() == 1 { "field" } else { "fields" }, missing.join(", "), name), span));
+        }
+        self.diagnostics.extend(diagnostics);
+        for (field, field_name, value_span) in hidden {
+            self.visible(&field, format!("Field '{}' of struct '{}'", field_name, name), name, value_span);
+        }
+
+        Some(Type::Named(name))
+    }
+
+    // the type of the block's value, void without one and never when a
+    // statement leaves it early
+    fn block(&mut self, body: &[AST], value: Option<&AST>) -> Option<Type> {
+        self.scopes.push(HashMap::new());
+        for node in body {
+            self.statement(node);
+        }
+        let block_type = match value {
+            Some(value) => self.expression(value),
+            None => Some(Type::void())
+        };
+        self.scopes.pop();
+
+        if body.iter().any(|node| matches!(node, AST::Return { .. } | AST::Break { .. } | AST::Continue { .. })) {
+            return Some(Type::never());
+        }
+        block_type
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name))
+    }
+
+    fn statement(&mut self, node: &AST) {
+        match node {
+            AST::VariableDeclaration { name, mutable, variable_type, value, span } => {
+                // the value can still see a variable it shadows
+                let value_type = self.expression(value);
+                if let (Some(expected), Some(found)) = (variable_type, &value_type) {
+                    if Self::unify(expected, found).is_none() {
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::MismatchedTypes, "Mismatched types", Self::value_span(value))
+                            .with_primary_label(format!("expected '{}', found '{}'", expected, found))
+                            .with_label(*span, format!("'{}' is declared as '{}' here", name, expected)));
+                    }
+                }
+
+                let scope = self.scopes.last_mut().unwrap();
+                if let Some(previous) = scope.get(name) {
+                    self.diagnostics.push(Diagnostic::error(ErrorCode::Redeclaration, format!("'{}' is already declared in this block", name), *span)
+                        .with_label(previous.span, "previous declaration")
+                        .with_help("rename the variable, or assign to the existing one if it is a 'var'"));
+                    return;
+                }
+
+                scope.insert(*name, Variable {
+                    mutable: *mutable,
+                    span: *span,
+                    variable_type: variable_type.clone().or(value_type)
+                });
+            }
+            AST::Assign { target, value, .. } => {
+                self.expression(value);
+                self.expression(target);
+
+                if let AST::Identifier { name, span } = target.as_ref() {
+                    if self.lookup(*name).is_none() && self.functions.contains_key(name) {
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::InvalidAssignmentTarget, format!("Cannot assign to function '{}'", name), *span)
+                            .with_help("only variables declared with 'var' can be assigned"));
+                    }
+                    if let Some(variable) = self.lookup(*name).filter(|variable| !variable.mutable) {
+                        let diagnostic = Diagnostic::error(ErrorCode::AssignToImmutable, format!("Cannot assign twice to immutable variable '{}'", name), *span);
+                        // arguments have no declaration to point at
+                        let diagnostic = if variable.span == Span::default() {
+                            diagnostic
+                                .with_note("function arguments are immutable")
+                                .with_help(format!("copy it into a mutable variable first, e.g. 'var {0} = {0};'", name))
+                        } else {
+                            diagnostic
+                                .with_label(variable.span, "declared with 'val'")
+                                .with_help(format!("declare it with 'var {}' to make it mutable", name))
+                        };
+                        self.diagnostics.push(diagnostic);
+                    }
+                }
+            }
+            AST::Return { value, .. } => {
+                self.expression(value);
+            }
+            AST::While { label, condition, body, .. } => {
+                self.expression(condition);
+                self.loops.push(*label);
+                self.discarded(body);
+                self.loops.pop();
+            }
+            AST::For { label, variable, iterable, body, span } => {
+                let element_type = self.iterable(iterable);
+
+                // the loop variable lives in a scope around the body
+                let mut scope = HashMap::new();
+                scope.insert(*variable, Variable { mutable: false, span: *span, variable_type: element_type });
+                self.scopes.push(scope);
+                self.loops.push(*label);
+                self.discarded(body);
+                self.loops.pop();
+                self.scopes.pop();
+            }
+            AST::Break { label, span } | AST::Continue { label, span } => {
+                let keyword = if matches!(node, AST::Break { .. }) { "break" } else { "continue" };
+                if self.loops.is_empty() {
+                    self.diagnostics.push(Diagnostic::error(ErrorCode::BreakOutsideLoop, format!("'{}' outside of a loop", keyword), *span)
+                        .with_primary_label(format!("cannot '{}' outside of a loop", keyword)));
+                } else if let Some(label) = label.filter(|label| !self.loops.contains(&Some(*label))) {
+                    self.diagnostics.push(Diagnostic::error(ErrorCode::UndeclaredLabel, format!("Use of undeclared label '{}'", label), *span)
+                        .with_help(format!("label the loop with '{}: while ...' or '{}: for ...'", label, label)));
+                }
+            }
+            node => self.discarded(node)
+        }
+    }
+
+    // an expression statement, branches may disagree since the value is dropped
+    fn discarded(&mut self, node: &AST) {
+        match node {
+            AST::Block { body, value, .. } => {
+                self.scopes.push(HashMap::new());
+                for node in body {
+                    self.statement(node);
+                }
+                if let Some(value) = value {
+                    self.discarded(value);
+                }
+                self.scopes.pop();
+            }
+            AST::If { condition, then_branch, else_branch, .. } => {
+                self.expression(condition);
+                self.discarded(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.discarded(else_branch);
+                }
+            }
+            AST::IfCompare { scrutinee, arms, .. } => {
+                let scrutinee_type = self.expression(scrutinee);
+                self.unreachable_arms(arms);
+                for arm in arms {
+                    self.pattern(arm, &scrutinee_type);
+                    self.discarded(&arm.value);
+                }
+            }
+            AST::Switch { scrutinee, arms, .. } => {
+                let scrutinee_type = self.expression(scrutinee);
+                self.unreachable_arms(arms);
+                for arm in arms {
+                    self.pattern(arm, &scrutinee_type);
+                    self.discarded(&arm.value);
+                }
+            }
+            node => {
+                self.expression(node);
+            }
+        }
+    }
+
+    // the type of the loop variable going over `iterable`
+    fn iterable(&mut self, iterable: &AST) -> Option<Type> {
+        if let AST::Range { start, end, .. } = iterable {
+            let start_type = self.expression(start);
+            let end_type = self.expression(end);
+            let (start_type, end_type) = (start_type?, end_type?);
+            let found = match Self::unify(&start_type, &end_type) {
+                Some(unified) if Self::is_integer(&unified) => return Some(unified),
+                Some(unified) => format!("expected an integer, found '{}'", unified),
+                None => format!("expected '{}', found '{}'", start_type, end_type)
+            };
+            self.diagnostics.push(Diagnostic::error(ErrorCode::MismatchedTypes, "Mismatched types", end.span())
+                .with_primary_label(found)
+                .with_note("both ends of a range must be the same integer type"));
+            return None;
+        }
+
+        let iterable_type = self.expression(iterable)?;
+        if let Type::Array(element) = iterable_type {
+            return Some(*element);
+        }
+        self.diagnostics.push(Diagnostic::error(ErrorCode::NotIterable, format!("'{}' is not iterable", iterable_type), iterable.span())
+            .with_note("a for loop goes over a range like '0..n' or an 'Array<T>'"));
+        None
+    }
+
+    fn pattern(&mut self, arm: &Arm, scrutinee_type: &Option<Type>) {
+        let Some(pattern) = &arm.pattern else { return };
+        let pattern_type = self.expression(pattern);
+
+        if let (Some(expected), Some(found)) = (scrutinee_type, pattern_type) {
+            if Self::unify(expected, &found).is_none() {
+                self.diagnostics.push(Diagnostic::error(ErrorCode::MismatchedTypes, "Mismatched types", pattern.span())
+                    .with_primary_label(format!("expected '{}', found '{}'", expected, found))
+                    .with_note("every pattern is compared with the value the arms are matched on"));
+            }
+        }
+    }
+
+    // the type every arm produces, the first arm with a known type decides
+    // what the others must produce
+    fn arms(&mut self, arms: &[Arm], scrutinee_type: &Option<Type>) -> Option<Type> {
+        let mut expected: Option<(Type, Span)> = None;
+        let mut compatible = true;
+        for arm in arms {
+            self.pattern(arm, scrutinee_type);
+            let Some(arm_type) = self.expression(&arm.value) else { continue };
+
+            match &expected {
+                None => expected = Some((arm_type, arm.span)),
+                Some((first, first_span)) => match Self::unify(first, &arm_type) {
+                    Some(unified) => expected = Some((unified, if first.is_never() { arm.span } else { *first_span })),
+                    None => {
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::IncompatibleBranches, "Arms have incompatible types", arm.span)
+                            .with_primary_label(format!("expected '{}', found '{}'", first, arm_type))
+                            .with_label(*first_span, format!("this is '{}'", first)));
+                        compatible = false;
+                    }
+                }
+            }
+        }
+
+        expected.filter(|_| compatible).map(|expected| expected.0)
+    }
+
+    // warns about arms after the wildcard or repeating an earlier constant,
+    // returns whether there is a wildcard
+    fn unreachable_arms(&mut self, arms: &[Arm]) -> bool {
+        let mut wildcard: Option<Span> = None;
+        let mut seen: HashMap<i128, Span> = HashMap::new();
+
+        for arm in arms {
+            if let Some(wildcard) = wildcard {
+                self.diagnostics.push(Diagnostic::warning(ErrorCode::UnreachableArm, "Unreachable arm", arm.span)
+                    .with_label(wildcard, "every value is already matched here"));
+                continue;
+            }
+
+            let Some(pattern) = &arm.pattern else {
+                wildcard = Some(arm.span);
+                continue;
+            };
+            if let Some(value) = pattern.integer_constant() {
+                if let Some(previous) = seen.get(&value) {
+                    self.diagnostics.push(Diagnostic::warning(ErrorCode::UnreachableArm, "Unreachable arm", arm.span)
+                        .with_label(*previous, "this value is already matched here"));
+                } else {
+                    seen.insert(value, arm.span);
+                }
+            }
+        }
+
+        wildcard.is_some()
+    }
+
+    // a call through a value of `callee_type`, checking the arguments against the parameters
+    fn call(&mut self, callee_type: Type, args: &[AST], arg_types: Vec<Option<Type>>, span: Span) -> Option<Type> {
+        let signature = callee_type.to_string();
+        let Type::Function { params, return_type } = callee_type else { return None };
+
+        if params.len() != args.len() {
+            self.diagnostics.push(Diagnostic::error(ErrorCode::ArgumentCount, format!("Expected {} {} but got {}", params.len(), if params.len() == 1 { "argument" } else { "arguments" }, args.len()), span)
+                .with_note(format!("the function is '{}'", signature)));
+            return Some(*return_type);
+        }
+        for ((param, arg), arg_type) in params.iter().zip(args).zip(arg_types) {
+            if let Some(arg_type) = arg_type.filter(|arg_type| Self::unify(param, arg_type).is_none()) {
+                self.diagnostics.push(Diagnostic::error(ErrorCode::MismatchedTypes, "Mismatched types", arg.span())
+                    .with_primary_label(format!("expected '{}', found '{}'", param, arg_type)));
+            }
+        }
+
+        Some(*return_type)
+    }
+
+    fn expression(&mut self, node: &AST) -> Option<Type> {
+        match node {
+            AST::Literal { value, span } => {
+                if let Literal::Int { value, suffix: Some(suffix) } = value {
+                    if *value > suffix.max_positive() {
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::InvalidNumber, format!("Literal out of range for {}", suffix.type_name()), *span)
+                            .with_note(format!("the largest {} is {}", suffix.type_name(), suffix.max_positive())));
+                    }
+                }
+                Some(Self::literal_type(value))
+            }
+            AST::Identifier { name, span } => {
+                if let Some(variable) = self.lookup(*name) {
+                    return variable.variable_type.clone();
+                }
+                if let Some(function) = self.functions.get(name) {
+                    return Some(function.clone());
+                }
+
+                let diagnostic = Diagnostic::error(ErrorCode::UnresolvedName, format!("Cannot find value '{}' in this scope", name), *span)
+                    .with_primary_label("not found in this scope");
+                self.diagnostics.push(if self.structs.contains_key(name) {
+                    diagnostic.with_help(format!("'{0}' is a struct, create one with '{0}(...)' or '{0} {{ ... }}'", name))
+                } else {
+                    diagnostic
+                });
+                None
+            }
+            AST::None { .. } => Some(Type::void()),
+            // the trailing `return` of a block like `{ return 2 }`
+            AST::Return { .. } | AST::Break { .. } | AST::Continue { .. } => {
+                self.statement(node);
+                Some(Type::never())
+            }
+            AST::Unary { operator, operand, .. } => {
+                // the lexer already made sure a negated literal like `-128i8` fits
+                let operand_type = match (operator, operand.as_ref()) {
+                    (UnaryOperator::Negate, AST::Literal { value, .. }) => Some(Self::literal_type(value)),
+                    _ => self.expression(operand)
+                };
+                match operator {
+                    UnaryOperator::Not => Some(Type::Named(Symbol::BOOL)),
+                    UnaryOperator::Negate | UnaryOperator::BitNot => operand_type
+                }
+            }
+            AST::Binary { operator, left, right, .. } => {
+                let left_type = self.expression(left);
+                let right_type = self.expression(right);
+                match operator {
+                    _ if operator.is_comparison() => Some(Type::Named(Symbol::BOOL)),
+                    BinaryOperator::And | BinaryOperator::Or => Some(Type::Named(Symbol::BOOL)),
+                    // shifting doesn't change the type of the left side
+                    BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => left_type,
+                    _ => Self::unify(&left_type?, &right_type?)
+                }
+            }
+            AST::Call { callee, args, span } => {
+                let arg_types = args.iter().map(|arg| self.expression(arg)).collect::<Vec<_>>();
+                match callee.as_ref() {
+                    AST::Identifier { name, span: name_span } if self.lookup(*name).is_none() && self.structs.contains_key(name) => {
+                        // calling a struct runs its constructor
+                        match self.structs[name].constructor.clone() {
+                            Some(constructor) => {
+                                self.visible(&constructor, format!("The constructor of struct '{}'", name), *name, *name_span);
+                                self.call(constructor.member_type, args, arg_types, *span);
+                            }
+                            None => self.diagnostics.push(Diagnostic::error(ErrorCode::UnknownMember, format!("Struct '{}' has no constructor", name), *name_span)
+                                .with_help(format!("create it with a struct literal, e.g. '{} {{ ... }}'", name)))
+                        }
+                        Some(Type::Named(*name))
+                    }
+                    // a method, or a field holding a function
+                    AST::Field { object, field, span: field_span } => {
+                        let object_type = self.expression(object);
+                        let member = self.member(object_type, *field, *field_span)?;
+                        self.call(member.member_type, args, arg_types, *span)
+                    }
+                    callee => {
+                        let callee_type = self.expression(callee)?;
+                        self.call(callee_type, args, arg_types, *span)
+                    }
+                }
+            }
+            AST::Field { object, field, span } => {
+                let object_type = self.expression(object);
+                let member = self.member(object_type.clone(), *field, *span)?;
+                if member.is_method {
+                    self.diagnostics.push(Diagnostic::error(ErrorCode::UnknownMember, format!("Method '{}' of struct '{}' is used as a value", field, object_type?), *span)
+                        .with_help(format!("call it, e.g. '.{}(...)'", field)));
+                    return None;
+                }
+                Some(member.member_type)
+            }
+            AST::StructLiteral { name, fields, span } => self.struct_literal(*name, fields, *span),
+            AST::Index { object, index, .. } => {
+                let object_type = self.expression(object);
+                self.expression(index);
+                match object_type {
+                    Some(Type::Array(element)) => Some(*element),
+                    _ => None
+                }
+            }
+            AST::Block { body, value, .. } => self.block(body, value.as_deref()),
+            AST::If { condition, then_branch, else_branch, span } => {
+                self.expression(condition);
+                let then_type = self.expression(then_branch);
+
+                let Some(else_branch) = else_branch else {
+                    if then_type.as_ref().is_some_and(Type::is_never) {
+                        return Some(Type::void());
+                    }
+                    if let Some(then_type) = then_type.as_ref().filter(|then_type| !then_type.is_void()) {
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::IncompatibleBranches, "'if' without 'else' used as a value", *span)
+                            .with_label(Self::value_span(then_branch), format!("this branch produces '{}'", then_type))
+                            .with_help("add an 'else' branch producing the same type"));
+                        return None;
+                    }
+                    return then_type;
+                };
+
+                let else_type = self.expression(else_branch);
+                let (then_type, else_type) = (then_type?, else_type?);
+                match Self::unify(&then_type, &else_type) {
+                    Some(unified) => Some(unified),
+                    None => {
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::IncompatibleBranches, "'if' and 'else' have incompatible types", Self::value_span(else_branch))
+                            .with_primary_label(format!("expected '{}', found '{}'", then_type, else_type))
+                            .with_label(Self::value_span(then_branch), format!("this is '{}'", then_type)));
+                        None
+                    }
+                }
+            }
+            AST::IfCompare { scrutinee, arms, span, .. } | AST::Switch { scrutinee, arms, span } => {
+                let scrutinee_type = self.expression(scrutinee);
+                let exhaustive = self.unreachable_arms(arms);
+                let arms_type = self.arms(arms, &scrutinee_type);
+                if exhaustive {
+                    return arms_type;
+                }
+
+                // nothing is produced when no pattern matches
+                match arms_type {
+                    Some(arms_type) if arms_type.is_never() => Some(Type::void()),
+                    Some(arms_type) if !arms_type.is_void() => {
+                        let keyword = if matches!(node, AST::Switch { .. }) { "switch" } else { "if" };
+                        self.diagnostics.push(Diagnostic::error(ErrorCode::NonExhaustiveSwitch, format!("'{}' without a '_' arm used as a value", keyword), *span)
+                            .with_note(format!("the arms produce '{}', but nothing is produced when no pattern matches", arms_type))
+                            .with_help("add a '_ -> ...' arm last"));
+                        None
+                    }
+                    arms_type => arms_type
+                }
+            }
+            _ => None
+        }
+    }
+
+    fn literal_type(value: &Literal) -> Type {
+        Type::Named(match value {
+            Literal::Int { suffix: Some(suffix), .. } | Literal::Float { suffix: Some(suffix), .. } => suffix.type_symbol(),
+            Literal::Int { .. } => Symbol::INTEGER,
+            Literal::Float { .. } => Symbol::FLOAT,
+            Literal::Str(_) => Symbol::STRING,
+            Literal::Char(_) => Symbol::CHAR
+        })
+    }
+
+    // the trailing value of a block, where a mismatch is easiest to see
+    fn value_span(node: &AST) -> Span {
+        match node {
+            AST::Block { value: Some(value), .. } => value.span(),
+            node => node.span()
+        }
+    }
+
+    fn is_integer(value_type: &Type) -> bool {
+        value_type.name().is_some_and(|name| INTEGER_TYPES.contains(&name))
+    }
+
+    fn is_float(value_type: &Type) -> bool {
+        value_type.name().is_some_and(|name| FLOAT_TYPES.contains(&name))
+    }
+
+    // the type both sides can be, None if they don't fit together
+    fn unify(left: &Type, right: &Type) -> Option<Type> {
+        if left == right || right.is_never() {
+            return Some(left.clone());
+        }
+        if left.is_never() {
+            return Some(right.clone());
+        }
+
+        // a literal takes the type of the other side
+        let fits = |literal: &Type, other: &Type| {
+            (*literal == Type::Named(Symbol::INTEGER) && Self::is_integer(other)) || (*literal == Type::Named(Symbol::FLOAT) && Self::is_float(other))
+        };
+        if fits(left, right) {
+            Some(right.clone())
+        } else if fits(right, left) {
+            Some(left.clone())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    // the codes the checker reports for `source`, which has to parse
+    fn codes(source: &str) -> Vec<ErrorCode> {
+        let mut parser = Parser::new(Lexer::new(source.to_string()));
+        let ast = parser.parse("test.dust".to_string());
+        assert!(parser.diagnostics().is_empty(), "syntax errors: {:?}", parser.diagnostics());
+        Checker::check(&ast).iter().map(|diagnostic| diagnostic.code).collect()
+    }
+
+    const POINT: &str = "P: struct {\n    pub x: int32;\n    priv y: int32;\n    pub construct(a: int32) { self.y = a; }\n    pub get: func(k: int32) -> int32 { return self.y + k; }\n    hidden: func { }\n}\n";
+
+    fn with_point(source: &str) -> Vec<ErrorCode> {
+        codes(&format!("{}{}", POINT, source))
+    }
+
+    #[test]
+    fn signed_literal_range_depends_on_the_sign() {
+        assert_eq!(codes("f: func { val x = -128i8; }"), vec![]);
+        assert_eq!(codes("f: func { val x = 127i8; }"), vec![]);
+        assert_eq!(codes("f: func { val x = 128i8; }"), vec![ErrorCode::InvalidNumber]);
+        assert_eq!(codes("f: func { val x = -(128i8); }"), vec![]);
+        assert_eq!(codes("f: func { val x = 2 - 128i8; }"), vec![ErrorCode::InvalidNumber]);
+    }
+
+    #[test]
+    fn variables_shadow_only_outside_their_block() {
+        assert_eq!(codes("f: func { val x = 1; { val x = \"text\"; } }"), vec![]);
+        assert_eq!(codes("f: func { val x = 1; { val x: bool = x; } }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func(x: int32) { val x = x + 1; }"), vec![]);
+        assert_eq!(codes("f: func { val x = 1; var x = 2; }"), vec![ErrorCode::Redeclaration]);
+    }
+
+    #[test]
+    fn only_var_can_be_assigned() {
+        assert_eq!(codes("f: func { var x = 1; x = 2; x += 3; }"), vec![]);
+        assert_eq!(codes("f: func { val x = 1; x = 2; }"), vec![ErrorCode::AssignToImmutable]);
+        assert_eq!(codes("f: func(x: int32) { x = 2; }"), vec![ErrorCode::AssignToImmutable]);
+        assert_eq!(codes("f: func { var x = 1; { val x = 2; x = 3; } }"), vec![ErrorCode::AssignToImmutable]);
+    }
+
+    #[test]
+    fn annotations_must_match_the_value() {
+        assert_eq!(codes("f: func { val x: int64 = 1; val y: float32 = 1.5; }"), vec![]);
+        assert_eq!(codes("f: func { val x: int32 = \"text\"; }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func { val x: bool = 1; }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func { val x: int8 = 1; var y: int16 = x; }"), vec![ErrorCode::MismatchedTypes]);
+    }
+
+    #[test]
+    fn if_branches_must_agree() {
+        assert_eq!(codes("f: func(c: bool) { val y: int32 = if c { 1 } else if !c { 2 } else { 3 }; }"), vec![]);
+        assert_eq!(codes("f: func(c: bool) { val y = if c { 1 } else { \"text\" }; }"), vec![ErrorCode::IncompatibleBranches]);
+        assert_eq!(codes("f: func(c: bool) { val y = if c { 1 }; }"), vec![ErrorCode::IncompatibleBranches]);
+        assert_eq!(codes("f: func(c: bool) { if c { 1 } else { \"text\" } }"), vec![]);
+    }
+
+    #[test]
+    fn if_compare_arms_must_agree() {
+        assert_eq!(codes("f: func(x: int32) { val y: int32 = if x < { 0 -> 1; 10 -> 2; _ -> 3; }; }"), vec![]);
+        assert_eq!(codes("f: func(x: int32) { val y = if x == { 1 -> 2; _ -> \"text\"; }; }"), vec![ErrorCode::IncompatibleBranches]);
+        assert_eq!(codes("f: func(x: int32) { val y = if x == { \"text\" -> 2; _ -> 3; }; }"), vec![ErrorCode::MismatchedTypes]);
+    }
+
+    #[test]
+    fn if_compare_used_as_a_value_needs_a_wildcard() {
+        assert_eq!(codes("f: func(x: int32) { val y = if x == { 1 -> 2; 3 -> 4; }; }"), vec![ErrorCode::NonExhaustiveSwitch]);
+        assert_eq!(codes("f: func(x: int32) { if x == { 1 -> 2; 3 -> 4; } }"), vec![]);
+        assert_eq!(codes("f: func(x: int32) { val y = if x == { 1 -> 2; _ -> 4; 5 -> 6; }; }"), vec![ErrorCode::UnreachableArm]);
+    }
+
+    #[test]
+    fn diverging_branches_fit_any_type() {
+        assert_eq!(codes("f: func(c: bool) -> int32 { val y: int32 = if c { 1 } else { return 2; }; return y; }"), vec![]);
+        assert_eq!(codes("f: func(c: bool) -> int32 { val y: int32 = if c { return 2 } else { 1 }; return y; }"), vec![]);
+        assert_eq!(codes("f: func(x: int32) -> int32 { val y: int32 = switch x { 1 -> { return 0; }; _ -> 2; }; return y; }"), vec![]);
+        assert_eq!(codes("f: func(x: int32) -> int32 { val y: int32 = if x == { 1 -> 2; _ -> { return 0; }; }; return y; }"), vec![]);
+        assert_eq!(codes("f: func(c: bool) { while c { val y: bool = if c { c } else { break; }; } }"), vec![]);
+        assert_eq!(codes("f: func(c: bool) { val y: int32 = if c { return; } else { \"text\" }; }"), vec![ErrorCode::MismatchedTypes]);
+    }
+
+    #[test]
+    fn arms_after_the_wildcard_or_a_repeated_value_are_unreachable() {
+        assert_eq!(codes("f: func(x: int32) { switch x { 1 -> g(); _ -> g(); 2 -> g(); } }\ng: func { }"), vec![ErrorCode::UnreachableArm]);
+        assert_eq!(codes("f: func(x: int32) { switch x { 1 -> g(); 2 -> g(); 1 -> g(); } }\ng: func { }"), vec![ErrorCode::UnreachableArm]);
+        assert_eq!(codes("f: func(x: int32) { switch x { 1 -> g(); -1 -> g(); _ -> g(); } }\ng: func { }"), vec![]);
+    }
+
+    #[test]
+    fn switch_used_as_a_value_needs_a_wildcard() {
+        assert_eq!(codes("f: func(x: int32) { val y = switch x { 1 -> 10; 2 -> 20; }; }"), vec![ErrorCode::NonExhaustiveSwitch]);
+        assert_eq!(codes("f: func(x: int32) { val y: int64 = switch x { 1 -> 10; _ -> 20; }; }"), vec![]);
+        assert_eq!(codes("f: func(x: int32) { switch x { 1 -> 10; 2 -> 20; } }"), vec![]);
+        assert_eq!(codes("f: func(x: int32) { val y = switch x { 1 -> 10; _ -> \"text\"; }; }"), vec![ErrorCode::IncompatibleBranches]);
+    }
+
+    #[test]
+    fn names_must_be_declared() {
+        assert_eq!(codes("f: func { val a = nope + 1; g(); }"), vec![ErrorCode::UnresolvedName, ErrorCode::UnresolvedName]);
+        assert_eq!(codes("f: func -> int32 { { val x = 1; } return x; }"), vec![ErrorCode::UnresolvedName]);
+        assert_eq!(codes("f: func { x = 1; }"), vec![ErrorCode::UnresolvedName]);
+        assert_eq!(codes("f: func -> int32 { val x = x; return 1; }"), vec![ErrorCode::UnresolvedName]);
+        assert_eq!(codes("f: func(x: int32) -> int32 { val g = f; return g(x) + h(); }\nh: func -> int32 { return 1; }"), vec![]);
+    }
+
+    #[test]
+    fn only_variables_can_be_assigned() {
+        assert_eq!(codes("g: func { }\nf: func { g = f; }"), vec![ErrorCode::InvalidAssignmentTarget]);
+        assert_eq!(codes("g: func { }\nf: func { var g = f; g = f; }"), vec![]);
+    }
+
+    #[test]
+    fn break_and_continue_need_a_loop() {
+        assert_eq!(codes("f: func(c: bool) { while c { break; } for i in 0..3 { continue; } }"), vec![]);
+        assert_eq!(codes("f: func { break; }"), vec![ErrorCode::BreakOutsideLoop]);
+        assert_eq!(codes("f: func(c: bool) { if c { continue; } }"), vec![ErrorCode::BreakOutsideLoop]);
+        assert_eq!(codes("f: func(c: bool) { a: while c { for i in 0..3 { break a; continue a; } } }"), vec![]);
+        assert_eq!(codes("f: func(c: bool) { a: while c { } while c { break a; } }"), vec![ErrorCode::UndeclaredLabel]);
+    }
+
+    #[test]
+    fn for_loops_go_over_ranges_and_arrays() {
+        assert_eq!(codes("f: func(n: int64) { for i in 0..=n { val j: int64 = i; } }"), vec![]);
+        assert_eq!(codes("f: func(a: Array<string>) { for s in a { val t: string = s; } }"), vec![]);
+        assert_eq!(codes("f: func(a: Array<string>) { for s in a { val t: int32 = s; } }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func(n: int8) { for i in 0u16..n { } }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func { for i in 0..\"end\" { } }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func(s: string) { for c in s { } }"), vec![ErrorCode::NotIterable]);
+    }
+
+    #[test]
+    fn loop_variables_are_immutable() {
+        assert_eq!(codes("f: func { for i in 0..3 { i = 1; } }"), vec![ErrorCode::AssignToImmutable]);
+    }
+
+    #[test]
+    fn members_are_checked_for_existence_and_visibility() {
+        assert_eq!(with_point("f: func(p: P) { p.z; }"), vec![ErrorCode::UnknownMember]);
+        assert_eq!(with_point("f: func(p: P) { p.y; p.hidden(); }"), vec![ErrorCode::PrivateMember, ErrorCode::PrivateMember]);
+        assert_eq!(codes("S: struct { a: int32; a: int32; construct() { } construct() { } }"), vec![ErrorCode::DuplicateMember, ErrorCode::DuplicateMember]);
+    }
+
+    #[test]
+    fn struct_literals_need_every_field() {
+        assert_eq!(codes("S: struct { pub a: int32; pub b: string; }\nf: func { val s = S { a: 1, b: \"b\" }; }"), vec![]);
+        assert_eq!(codes("S: struct { pub a: int32; pub b: string; }\nf: func { val s = S { a: 1 }; }"), vec![ErrorCode::MissingFields]);
+        assert_eq!(codes("S: struct { pub a: int32; }\nf: func { val s = S { a: \"a\", c: 1 }; }"), vec![ErrorCode::MismatchedTypes, ErrorCode::UnknownMember]);
+        assert_eq!(codes("f: func { val s = T { }; }"), vec![ErrorCode::UnknownStruct]);
+    }
+
+    #[test]
+    fn only_struct_fields_can_be_read() {
+        assert_eq!(codes("f: func { val x = 1; x.y = 2; }"), vec![ErrorCode::UnknownMember]);
+        assert_eq!(codes("f: func(s: string) -> int32 { return s.len; }"), vec![ErrorCode::UnknownMember]);
+        assert_eq!(codes("f: func(a: Array<int32>) { a.len(); }"), vec![ErrorCode::UnknownMember]);
+        assert_eq!(with_point("f: func(p: P) { val g = p.get; }"), vec![ErrorCode::UnknownMember]);
+        assert_eq!(with_point("f: func(p: P) { p.get = 1; }"), vec![ErrorCode::UnknownMember]);
+        // a value whose type can't be inferred, without an error explaining why
+        assert_eq!(codes("f: func { val v = (1 + \"a\").x; }"), vec![ErrorCode::UnknownMember]);
+        // an undeclared receiver is reported once
+        assert_eq!(codes("f: func -> int32 { return mystery.x; }"), vec![ErrorCode::UnresolvedName]);
+    }
+
+    #[test]
+    fn constructor_calls_are_checked_against_the_constructor() {
+        assert_eq!(with_point("f: func { val p: P = P(1); }"), vec![]);
+        assert_eq!(with_point("f: func { val p = P(\"a\", 2, 3); }"), vec![ErrorCode::ArgumentCount]);
+        assert_eq!(with_point("f: func { val p = P(\"a\"); }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("Q: struct { pub x: int32; }\nf: func { val q = Q(); }"), vec![ErrorCode::UnknownMember]);
+    }
+
+    #[test]
+    fn method_calls_are_checked_against_the_method() {
+        assert_eq!(with_point("f: func(p: P) -> int32 { return p.get(1); }"), vec![]);
+        assert_eq!(with_point("f: func(p: P) -> int32 { return p.get(); }"), vec![ErrorCode::ArgumentCount]);
+        assert_eq!(with_point("f: func(p: P) -> int32 { return p.get(\"k\"); }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(with_point("f: func(p: P) { val s: string = p.get(1); }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(with_point("mk: func -> P { return P(1); }\nf: func -> int32 { return mk().get(1) + mk().x; }"), vec![]);
+        assert_eq!(with_point("mk: func -> P { return P(1); }\nf: func { mk().get(); }"), vec![ErrorCode::ArgumentCount]);
+    }
+
+    #[test]
+    fn functions_are_values_of_their_function_type() {
+        let double = "double: func(x: int32) -> int32 { return x * 2; }\n";
+        assert_eq!(codes(&format!("{}f: func {{ val g: func(int32): int32 = double; var h = g; h = double; }}", double)), vec![]);
+        assert_eq!(codes(&format!("{}f: func {{ val g: func(string): int32 = double; }}", double)), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes(&format!("{}f: func {{ val g: func(int32) = double; }}", double)), vec![ErrorCode::MismatchedTypes]);
+    }
+
+    #[test]
+    fn function_values_are_called_through_their_type() {
+        let apply = "apply: func(g: func(int32): int32, v: int32) -> int32 { return g(v); }\ndouble: func(x: int32) -> int32 { return x * 2; }\n";
+        assert_eq!(codes(&format!("{}f: func -> int32 {{ return apply(double, 1); }}", apply)), vec![]);
+        assert_eq!(codes(&format!("{}f: func -> int32 {{ return apply(apply, 1); }}", apply)), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func(g: func(int32): int32) -> int32 { return g(); }"), vec![ErrorCode::ArgumentCount]);
+        assert_eq!(codes("f: func(g: func(int32): string) { val x: int32 = g(1); }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("S: struct { pub f: func(int32); }\ng: func(s: S) { s.f(1); s.f(\"a\"); }"), vec![ErrorCode::MismatchedTypes]);
+    }
+
+    #[test]
+    fn nested_arrays_keep_their_element_types() {
+        assert_eq!(codes("f: func(m: Array<Array<int32>>) { for row in m { for x in row { val y: int32 = x; } } }"), vec![]);
+        assert_eq!(codes("f: func(m: Array<Array<int32>>) { val y: int32 = m[0][1]; val r: Array<int32> = m[0]; }"), vec![]);
+        assert_eq!(codes("f: func(m: Array<Array<int32>>) { val r: Array<string> = m[0]; }"), vec![ErrorCode::MismatchedTypes]);
+        assert_eq!(codes("f: func(a: Map<string, int32>, b: Map<string, int64>) { val c: Map<string, int32> = a; val d: Map<string, int32> = b; }"), vec![ErrorCode::MismatchedTypes]);
+    }
+}
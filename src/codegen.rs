@@ -0,0 +1,795 @@
+use crate::checker::Receivers;
+use crate::lexer::{Literal, Span};
+use crate::pair::Pair;
+use crate::parser::{Arm, BinaryOperator, Type, UnaryOperator, AST};
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::Write;
+
+// fewer cases than this are compared one by one
+const MIN_JUMP_TABLE_CASES: i128 = 4;
+
+// fields are 8 byte slots in declaration order
+#[derive(Debug, Default)]
+struct StructLayout {
+    fields: Vec<Symbol>,
+    methods: HashSet<Symbol>,
+}
+
+impl StructLayout {
+    fn offset(&self, field: Symbol) -> Option<usize> {
+        self.fields.iter().position(|name| *name == field).map(|index| index * 8)
+    }
+}
+
+// pseudo x86-64 assembly for debugging. Every expression leaves its value in
+// rax, arguments are pushed right to left and popped by the caller. Methods
+// take `self` as their first argument, structs live on the heap and are
+// allocated by calling `alloc` with their size.
+pub struct CodeGen<W: Write> {
+    out: W,
+    filename: String,
+    // functions defined in the file, calling them is a direct `call`
+    functions: HashSet<Symbol>,
+    structs: HashMap<Symbol, StructLayout>,
+    // the struct behind every field access and method call, as resolved by the checker
+    receivers: Receivers,
+    // stack slots of the current function as offsets below rbp
+    locals: Vec<(Symbol, usize)>,
+    // bytes of the current frame handed out to locals so far
+    frame_size: usize,
+    strings: Vec<String>,
+    labels: usize,
+    // label, continue target and break target of the loops being generated, innermost last
+    loops: Vec<(Option<Symbol>, String, String)>,
+}
+
+impl<W: Write> CodeGen<W> {
+    pub fn new(out: W, receivers: Receivers) -> CodeGen<W> {
+        CodeGen {
+            out,
+            filename: String::new(),
+            functions: HashSet::new(),
+            structs: HashMap::new(),
+            receivers,
+            locals: Vec::new(),
+            frame_size: 0,
+            strings: Vec::new(),
+            labels: 0,
+            loops: Vec::new()
+        }
+    }
+
+    pub fn generate(&mut self, ast: AST) -> io::Result<()> {
+        let AST::File { child, filename } = ast else {
+            panic!("You can only generate pseudo-asm for files");
+        };
+        self.filename = filename;
+
+        for node in &child {
+            match node {
+                AST::FunctionDefinition { name, .. } => {
+                    self.functions.insert(*name);
+                }
+                AST::StructDefinition { name, fields, methods, .. } => {
+                    let layout = StructLayout {
+                        fields: fields.iter().map(|field| field.name).collect(),
+                        methods: methods.iter().filter_map(|method| match method.function {
+                            AST::FunctionDefinition { name, .. } => Some(name),
+                            _ => None
+                        }).collect()
+                    };
+                    self.structs.insert(*name, layout);
+                }
+                _ => {}
+            }
+        }
+
+        for node in child {
+            match node {
+                AST::FunctionDefinition { name, args, body, .. } => {
+                    self.function(self.name_with_file(name), args, body)?;
+                }
+                AST::StructDefinition { name, constructors, methods, .. } => {
+                    let this = Pair(Symbol::SELF, Type::Named(name));
+                    for constructor in constructors {
+                        let AST::FunctionDefinition { args, mut body, .. } = constructor.function else { continue };
+                        // the caller allocates, the constructor fills in and returns `self`
+                        body.push(AST::Return {
+                            value: Box::new(AST::Identifier { name: this.0, span: constructor.span }),
+                            span: constructor.span
+                        });
+                        self.function(self.member_name(name, Symbol::CONSTRUCT), std::iter::once(this.clone()).chain(args).collect(), body)?;
+                    }
+                    for method in methods {
+                        let AST::FunctionDefinition { name: method_name, args, body, .. } = method.function else { continue };
+                        self.function(self.member_name(name, method_name), std::iter::once(this.clone()).chain(args).collect(), body)?;
+                    }
+                }
+                _ => {
+                    unimplemented!();
+                }
+            }
+        }
+
+        for (index, string) in self.strings.iter().enumerate() {
+            writeln!(self.out, "string_{}: db {:?}, 0", index, string)?;
+        }
+
+        Ok(())
+    }
+
+    fn name_with_file(&self, name: Symbol) -> String {
+        let mut mangled = self.filename.to_string();
+        mangled = mangled.replace("/", "_");
+        mangled = mangled.replace(".", "_");
+        mangled.push_str("__");
+        mangled.push_str(name.as_str());
+        mangled.push_str("__");
+        mangled
+    }
+
+    fn member_name(&self, structure: Symbol, member: Symbol) -> String {
+        format!("{}{}__", self.name_with_file(structure), member)
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn function(&mut self, label: String, args: Vec<Pair<Symbol, Type>>, body: Vec<AST>) -> io::Result<()> {
+        writeln!(self.out, "{}:", label)?;
+        writeln!(self.out, "\tpush rbp")?;
+        writeln!(self.out, "\tmov rbp, rsp")?;
+        // one slot per argument and per variable declared anywhere in the body
+        let slots = args.len() + body.iter().map(Self::count_variables).sum::<usize>();
+        writeln!(self.out, "\tsub rsp, {}", slots * 8)?;
+
+        // copy the arguments into the frame, above rbp are the saved rbp and the return address
+        self.locals.clear();
+        self.frame_size = 0;
+        for (index, arg) in args.into_iter().enumerate() {
+            let offset = self.allocate_slot(arg.0);
+            writeln!(self.out, "\tmov rax, [rbp + {}]", 16 + index * 8)?;
+            writeln!(self.out, "\tmov [rbp - {}], rax", offset)?;
+        }
+
+        let returns = matches!(body.last(), Some(AST::Return { .. }));
+        for node in body {
+            self.statement(node)?;
+        }
+        if !returns {
+            self.epilogue()?;
+        }
+
+        Ok(())
+    }
+
+    // for loops keep their end or array and index in hidden slots, compare chains their scrutinee
+    fn count_variables(node: &AST) -> usize {
+        let own = match node {
+            AST::VariableDeclaration { .. } | AST::IfCompare { .. } | AST::Switch { .. } => 1,
+            AST::For { .. } => 3,
+            _ => 0
+        };
+        own + node.children().into_iter().map(Self::count_variables).sum::<usize>()
+    }
+
+    fn allocate_slot(&mut self, name: Symbol) -> usize {
+        self.frame_size += 8;
+        self.locals.push((name, self.frame_size));
+        self.frame_size
+    }
+
+    // a slot no name refers to
+    fn allocate_hidden_slot(&mut self) -> usize {
+        self.frame_size += 8;
+        self.frame_size
+    }
+
+    fn epilogue(&mut self) -> io::Result<()> {
+        writeln!(self.out, "\tmov rsp, rbp")?;
+        writeln!(self.out, "\tpop rbp")?;
+        writeln!(self.out, "\tret")
+    }
+
+    fn statement(&mut self, node: AST) -> io::Result<()> {
+        match node {
+            AST::Return { value, .. } => {
+                if !matches!(*value, AST::None { .. }) {
+                    self.expression(*value)?;
+                }
+                self.epilogue()
+            }
+            AST::VariableDeclaration { name, mutable, value, .. } => {
+                self.expression(*value)?;
+                let offset = self.allocate_slot(name);
+                writeln!(self.out, "\tmov [rbp - {}], rax ; {} {}", offset, if mutable { "var" } else { "val" }, name)
+            }
+            AST::Assign { target, operator, value, span } => {
+                // `a op= b` is stored as `a = a op b`
+                let value = match operator {
+                    Some(operator) => AST::Binary {
+                        operator,
+                        left: target.clone(),
+                        right: value,
+                        span
+                    },
+                    None => *value
+                };
+                self.assign(*target, value)
+            }
+            AST::While { label, condition, body, .. } => {
+                let start = self.new_label();
+                let end = self.new_label();
+
+                writeln!(self.out, "{}:", start)?;
+                self.expression(*condition)?;
+                writeln!(self.out, "\tcmp rax, 0")?;
+                writeln!(self.out, "\tje {}", end)?;
+                self.loop_body(label, *body, &start, &end)?;
+                writeln!(self.out, "\tjmp {}", start)?;
+                writeln!(self.out, "{}:", end)
+            }
+            AST::For { label, variable, iterable, body, .. } => self.for_loop(label, variable, *iterable, *body),
+            AST::Break { label, .. } | AST::Continue { label, .. } => {
+                // the checker made sure there is a matching loop
+                let is_break = matches!(node, AST::Break { .. });
+                let (_, next, end) = self.loops.iter().rev().find(|target| label.is_none() || target.0 == label).unwrap();
+                writeln!(self.out, "\tjmp {}", if is_break { end } else { next })
+            }
+            // expression statement, the value is dropped
+            node => self.expression(node)
+        }
+    }
+
+    // `continue` jumps to `next`, `break` to `end`
+    fn loop_body(&mut self, label: Option<Symbol>, body: AST, next: &str, end: &str) -> io::Result<()> {
+        self.loops.push((label, next.to_string(), end.to_string()));
+        self.expression(body)?;
+        self.loops.pop();
+        Ok(())
+    }
+
+    fn for_loop(&mut self, label: Option<Symbol>, variable: Symbol, iterable: AST, body: AST) -> io::Result<()> {
+        let start = self.new_label();
+        let next = self.new_label();
+        let end = self.new_label();
+        // the loop variable is only visible in the body
+        let scope = self.locals.len();
+
+        match iterable {
+            AST::Range { start: first, end: last, inclusive, .. } => {
+                let counter = self.allocate_slot(variable);
+                let limit = self.allocate_hidden_slot();
+                self.expression(*first)?;
+                writeln!(self.out, "\tmov [rbp - {}], rax ; for {}", counter, variable)?;
+                self.expression(*last)?;
+                writeln!(self.out, "\tmov [rbp - {}], rax", limit)?;
+
+                writeln!(self.out, "{}:", start)?;
+                writeln!(self.out, "\tmov rax, [rbp - {}]", counter)?;
+                writeln!(self.out, "\tcmp rax, [rbp - {}]", limit)?;
+                writeln!(self.out, "\t{} {}", if inclusive { "jg" } else { "jge" }, end)?;
+                self.loop_body(label, body, &next, &end)?;
+                writeln!(self.out, "{}:", next)?;
+                writeln!(self.out, "\tmov rax, [rbp - {}]", counter)?;
+                writeln!(self.out, "\tadd rax, 1")?;
+                writeln!(self.out, "\tmov [rbp - {}], rax", counter)?;
+            }
+            // arrays point at their first element, the length is stored in the word before it
+            iterable => {
+                let array = self.allocate_hidden_slot();
+                let index = self.allocate_hidden_slot();
+                let element = self.allocate_slot(variable);
+                self.expression(iterable)?;
+                writeln!(self.out, "\tmov [rbp - {}], rax", array)?;
+                writeln!(self.out, "\tmov qword [rbp - {}], 0", index)?;
+
+                writeln!(self.out, "{}:", start)?;
+                writeln!(self.out, "\tmov rax, [rbp - {}]", index)?;
+                writeln!(self.out, "\tmov rcx, [rbp - {}]", array)?;
+                writeln!(self.out, "\tcmp rax, [rcx - 8]")?;
+                writeln!(self.out, "\tjge {}", end)?;
+                writeln!(self.out, "\tmov rax, [rcx + rax * 8]")?;
+                writeln!(self.out, "\tmov [rbp - {}], rax ; for {}", element, variable)?;
+                self.loop_body(label, body, &next, &end)?;
+                writeln!(self.out, "{}:", next)?;
+                writeln!(self.out, "\tmov rax, [rbp - {}]", index)?;
+                writeln!(self.out, "\tadd rax, 1")?;
+                writeln!(self.out, "\tmov [rbp - {}], rax", index)?;
+            }
+        }
+
+        writeln!(self.out, "\tjmp {}", start)?;
+        writeln!(self.out, "{}:", end)?;
+        self.locals.truncate(scope);
+        Ok(())
+    }
+
+    fn assign(&mut self, target: AST, value: AST) -> io::Result<()> {
+        self.expression(value)?;
+        match target {
+            AST::Identifier { name, .. } => {
+                let offset = self.local(name).expect("the checker only lets variables be assigned");
+                writeln!(self.out, "\tmov [rbp - {}], rax", offset)
+            }
+            AST::Field { object, field, span } => {
+                let (offset, comment) = self.field_offset(self.receiver(span), field);
+                writeln!(self.out, "\tpush rax")?;
+                self.expression(*object)?;
+                writeln!(self.out, "\tpop rcx")?;
+                writeln!(self.out, "\tmov [rax + {}], rcx{}", offset, comment)
+            }
+            AST::Index { object, index, .. } => {
+                writeln!(self.out, "\tpush rax")?;
+                self.expression(*index)?;
+                writeln!(self.out, "\tpush rax")?;
+                self.expression(*object)?;
+                writeln!(self.out, "\tpop rcx")?;
+                writeln!(self.out, "\tpop rdx")?;
+                writeln!(self.out, "\tmov [rax + rcx * 8], rdx")
+            }
+            _ => {
+                panic!("Unreachable");
+            }
+        }
+    }
+
+    fn local(&self, name: Symbol) -> Option<usize> {
+        // the latest binding wins
+        self.locals.iter().rev().find(|local| local.0 == name).map(|local| local.1)
+    }
+
+    fn expression(&mut self, node: AST) -> io::Result<()> {
+        match node {
+            AST::Literal { value, .. } => match value {
+                Literal::Int { value, .. } => writeln!(self.out, "\tmov rax, {}", value),
+                Literal::Float { value, .. } => writeln!(self.out, "\tmov rax, {:#x} ; {}", value.to_bits(), value),
+                Literal::Char(c) => writeln!(self.out, "\tmov rax, {}", c as u32),
+                Literal::Str(string) => {
+                    self.strings.push(string);
+                    writeln!(self.out, "\tlea rax, [string_{}]", self.strings.len() - 1)
+                }
+            },
+            AST::Identifier { name, .. } => {
+                if let Some(offset) = self.local(name) {
+                    writeln!(self.out, "\tmov rax, [rbp - {}]", offset)
+                } else {
+                    // the checker rejects names that are neither
+                    writeln!(self.out, "\tlea rax, [{}]", self.name_with_file(name))
+                }
+            }
+            AST::Unary { operator, operand, .. } => {
+                self.expression(*operand)?;
+                match operator {
+                    UnaryOperator::Negate => writeln!(self.out, "\tneg rax"),
+                    UnaryOperator::BitNot => writeln!(self.out, "\tnot rax"),
+                    UnaryOperator::Not => {
+                        writeln!(self.out, "\tcmp rax, 0")?;
+                        writeln!(self.out, "\tsete al")?;
+                        writeln!(self.out, "\tmovzx rax, al")
+                    }
+                }
+            }
+            AST::Binary { operator: operator @ (BinaryOperator::And | BinaryOperator::Or), left, right, .. } => {
+                // short circuit: skip the right side once the left decides the result
+                let end = self.new_label();
+                self.expression(*left)?;
+                writeln!(self.out, "\tcmp rax, 0")?;
+                writeln!(self.out, "\t{} {}", if operator == BinaryOperator::And { "je" } else { "jne" }, end)?;
+                self.expression(*right)?;
+                writeln!(self.out, "{}:", end)?;
+                writeln!(self.out, "\tcmp rax, 0")?;
+                writeln!(self.out, "\tsetne al")?;
+                writeln!(self.out, "\tmovzx rax, al")
+            }
+            AST::Binary { operator, left, right, .. } => {
+                self.expression(*left)?;
+                writeln!(self.out, "\tpush rax")?;
+                self.expression(*right)?;
+                writeln!(self.out, "\tmov rcx, rax")?;
+                writeln!(self.out, "\tpop rax")?;
+                self.binary(operator)
+            }
+            AST::Call { callee, args, .. } => {
+                let mut count = args.len();
+                for arg in args.into_iter().rev() {
+                    self.expression(arg)?;
+                    writeln!(self.out, "\tpush rax")?;
+                }
+
+                let method = match callee.as_ref() {
+                    AST::Field { field, span, .. } => {
+                        let structure = self.receiver(*span);
+                        self.structs[&structure].methods.contains(field).then_some(structure)
+                    }
+                    _ => None
+                };
+                match *callee {
+                    AST::Identifier { name, .. } if self.local(name).is_none() && self.functions.contains(&name) => {
+                        writeln!(self.out, "\tcall {}", self.name_with_file(name))?;
+                    }
+                    AST::Identifier { name, .. } if self.local(name).is_none() && self.structs.contains_key(&name) => {
+                        self.allocate(name)?;
+                        writeln!(self.out, "\tpush rax")?;
+                        writeln!(self.out, "\tcall {}", self.member_name(name, Symbol::CONSTRUCT))?;
+                        count += 1;
+                    }
+                    // `self` goes before the other arguments
+                    AST::Field { object, field, .. } if method.is_some() => {
+                        self.expression(*object)?;
+                        writeln!(self.out, "\tpush rax")?;
+                        writeln!(self.out, "\tcall {}", self.member_name(method.unwrap(), field))?;
+                        count += 1;
+                    }
+                    // anything else evaluates to the address of a function
+                    callee => {
+                        self.expression(callee)?;
+                        writeln!(self.out, "\tcall rax")?;
+                    }
+                }
+
+                if count > 0 {
+                    writeln!(self.out, "\tadd rsp, {}", count * 8)?;
+                }
+                Ok(())
+            }
+            AST::Field { object, field, span } => {
+                let (offset, comment) = self.field_offset(self.receiver(span), field);
+                self.expression(*object)?;
+                writeln!(self.out, "\tmov rax, [rax + {}]{}", offset, comment)
+            }
+            AST::StructLiteral { name, fields, .. } => {
+                // the new struct stays on the stack while its fields are filled in
+                self.allocate(name)?;
+                writeln!(self.out, "\tpush rax")?;
+                for Pair(field, value) in fields {
+                    let (offset, comment) = self.field_offset(name, field);
+                    self.expression(value)?;
+                    writeln!(self.out, "\tmov rcx, [rsp]")?;
+                    writeln!(self.out, "\tmov [rcx + {}], rax{}", offset, comment)?;
+                }
+                writeln!(self.out, "\tpop rax")
+            }
+            AST::Block { body, value, .. } => {
+                // variables go out of scope at the end of the block, their slots stay reserved
+                let scope = self.locals.len();
+                for node in body {
+                    self.statement(node)?;
+                }
+                if let Some(value) = value {
+                    self.expression(*value)?;
+                }
+                self.locals.truncate(scope);
+                Ok(())
+            }
+            AST::If { condition, then_branch, else_branch, .. } => {
+                let otherwise = self.new_label();
+                let end = self.new_label();
+
+                self.expression(*condition)?;
+                writeln!(self.out, "\tcmp rax, 0")?;
+                writeln!(self.out, "\tje {}", otherwise)?;
+                self.expression(*then_branch)?;
+                writeln!(self.out, "\tjmp {}", end)?;
+                writeln!(self.out, "{}:", otherwise)?;
+                if let Some(else_branch) = else_branch {
+                    self.expression(*else_branch)?;
+                }
+                writeln!(self.out, "{}:", end)
+            }
+            AST::IfCompare { scrutinee, operator, arms, .. } => {
+                // the scrutinee stays in a slot while the arms are tried in order,
+                // so `break` and `continue` can jump out of an arm
+                let end = self.new_label();
+                let slot = self.allocate_hidden_slot();
+                self.expression(*scrutinee)?;
+                writeln!(self.out, "\tmov [rbp - {}], rax", slot)?;
+
+                for arm in arms {
+                    let next = self.new_label();
+                    if let Some(pattern) = arm.pattern {
+                        self.expression(pattern)?;
+                        writeln!(self.out, "\tmov rcx, rax")?;
+                        writeln!(self.out, "\tmov rax, [rbp - {}]", slot)?;
+                        writeln!(self.out, "\tcmp rax, rcx")?;
+                        writeln!(self.out, "\t{} {}", Self::jump_unless(operator), next)?;
+                    }
+                    self.expression(arm.value)?;
+                    writeln!(self.out, "\tjmp {}", end)?;
+                    writeln!(self.out, "{}:", next)?;
+                }
+
+                writeln!(self.out, "{}:", end)
+            }
+            AST::Switch { scrutinee, arms, span } => {
+                // arms after the wildcard are never taken
+                let reachable = arms.iter().position(|arm| arm.pattern.is_none()).map_or(arms.len(), |wildcard| wildcard + 1);
+                let mut arms = arms;
+                arms.truncate(reachable);
+
+                let cases = arms.iter().filter_map(|arm| arm.pattern.as_ref()).map(|pattern| pattern.integer_constant()).collect::<Option<Vec<_>>>();
+                match cases {
+                    Some(cases) if Self::is_dense(&cases) => self.jump_table(*scrutinee, arms, &cases),
+                    _ => self.expression(AST::IfCompare {
+                        scrutinee,
+                        operator: BinaryOperator::Equal,
+                        arms,
+                        span
+                    })
+                }
+            }
+            // `_` has no value
+            AST::None { .. } => Ok(()),
+            AST::Index { object, index, .. } => {
+                self.expression(*object)?;
+                writeln!(self.out, "\tpush rax")?;
+                self.expression(*index)?;
+                writeln!(self.out, "\tmov rcx, rax")?;
+                writeln!(self.out, "\tpop rax")?;
+                writeln!(self.out, "\tmov rax, [rax + rcx * 8]")
+            }
+            _ => {
+                panic!("Unreachable");
+            }
+        }
+    }
+
+    // a jump table pays off for enough cases that cover at least half of their range
+    fn is_dense(cases: &[i128]) -> bool {
+        let distinct = cases.iter().collect::<HashSet<_>>().len() as i128;
+        let (Some(min), Some(max)) = (cases.iter().min(), cases.iter().max()) else { return false };
+        distinct >= MIN_JUMP_TABLE_CASES && max - min < distinct * 2
+    }
+
+    // `cases` are the constant patterns of `arms` in order, the wildcard if any is last
+    fn jump_table(&mut self, scrutinee: AST, arms: Vec<Arm>, cases: &[i128]) -> io::Result<()> {
+        let min = *cases.iter().min().unwrap();
+        let max = *cases.iter().max().unwrap();
+        let table = self.new_label();
+        let default = self.new_label();
+        let end = self.new_label();
+
+        let labels = cases.iter().map(|_| self.new_label()).collect::<Vec<_>>();
+        // the first arm matching a value takes it
+        let mut entries = vec![default.clone(); (max - min + 1) as usize];
+        for (case, label) in cases.iter().zip(&labels).rev() {
+            entries[(case - min) as usize] = label.clone();
+        }
+
+        self.expression(scrutinee)?;
+        if min != 0 {
+            writeln!(self.out, "\tsub rax, {}", min)?;
+        }
+        // values below `min` wrap around and are above the table as well
+        writeln!(self.out, "\tcmp rax, {}", max - min)?;
+        writeln!(self.out, "\tja {}", default)?;
+        writeln!(self.out, "\tlea rcx, [{}]", table)?;
+        writeln!(self.out, "\tjmp [rcx + rax * 8]")?;
+        writeln!(self.out, "{}:", table)?;
+        writeln!(self.out, "\tdq {}", entries.join(", "))?;
+
+        let mut has_default = false;
+        for (index, arm) in arms.into_iter().enumerate() {
+            if arm.pattern.is_none() {
+                has_default = true;
+                writeln!(self.out, "{}:", default)?;
+            } else {
+                writeln!(self.out, "{}:", labels[index])?;
+            }
+            self.expression(arm.value)?;
+            writeln!(self.out, "\tjmp {}", end)?;
+        }
+        if !has_default {
+            writeln!(self.out, "{}:", default)?;
+        }
+
+        writeln!(self.out, "{}:", end)
+    }
+
+    // the struct whose member is used at `span`, the checker rejects every
+    // access it can't resolve
+    fn receiver(&self, span: Span) -> Symbol {
+        self.receivers[&span]
+    }
+
+    // the offset of a field and a comment naming it
+    fn field_offset(&self, structure: Symbol, field: Symbol) -> (usize, String) {
+        let offset = self.structs[&structure].offset(field).expect("the checker only lets fields through");
+        (offset, format!(" ; {}.{}", structure, field))
+    }
+
+    // leaves a pointer to a new `structure` in rax
+    fn allocate(&mut self, structure: Symbol) -> io::Result<()> {
+        writeln!(self.out, "\tpush {}", self.structs[&structure].fields.len() * 8)?;
+        writeln!(self.out, "\tcall alloc")?;
+        writeln!(self.out, "\tadd rsp, 8")
+    }
+
+    // the jump taken when the comparison `rax operator rcx` is false
+    fn jump_unless(operator: BinaryOperator) -> &'static str {
+        match operator {
+            BinaryOperator::Equal => "jne",
+            BinaryOperator::NotEqual => "je",
+            BinaryOperator::Less => "jge",
+            BinaryOperator::LessEqual => "jg",
+            BinaryOperator::Greater => "jle",
+            BinaryOperator::GreaterEqual => "jl",
+            _ => unreachable!()
+        }
+    }
+
+    // left operand in rax, right in rcx
+    fn binary(&mut self, operator: BinaryOperator) -> io::Result<()> {
+        let instruction = match operator {
+            BinaryOperator::Add => "add rax, rcx",
+            BinaryOperator::Subtract => "sub rax, rcx",
+            BinaryOperator::Multiply => "imul rax, rcx",
+            BinaryOperator::BitAnd => "and rax, rcx",
+            BinaryOperator::BitOr => "or rax, rcx",
+            BinaryOperator::BitXor => "xor rax, rcx",
+            BinaryOperator::ShiftLeft => "shl rax, cl",
+            BinaryOperator::ShiftRight => "sar rax, cl",
+            BinaryOperator::Divide | BinaryOperator::Remainder => {
+                writeln!(self.out, "\tcqo")?;
+                writeln!(self.out, "\tidiv rcx")?;
+                if operator == BinaryOperator::Remainder {
+                    writeln!(self.out, "\tmov rax, rdx")?;
+                }
+                return Ok(());
+            }
+            comparison => {
+                let set = match comparison {
+                    BinaryOperator::Equal => "sete",
+                    BinaryOperator::NotEqual => "setne",
+                    BinaryOperator::Less => "setl",
+                    BinaryOperator::LessEqual => "setle",
+                    BinaryOperator::Greater => "setg",
+                    BinaryOperator::GreaterEqual => "setge",
+                    _ => unreachable!()
+                };
+                writeln!(self.out, "\tcmp rax, rcx")?;
+                writeln!(self.out, "\t{} al", set)?;
+                return writeln!(self.out, "\tmovzx rax, al");
+            }
+        };
+
+        writeln!(self.out, "\t{}", instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    const POINT: &str = "P: struct {\n    pub x: int32;\n    pub y: int32;\n    pub construct(x: int32) { self.x = x; }\n    pub sum: func -> int32 { return self.x + self.y; }\n    pub f: func(a: int32) -> int32;\n}\nmk: func -> P { return P(1); }\n";
+
+    fn assembly(source: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(source.to_string()));
+        let ast = parser.parse("test.dust".to_string());
+        assert!(parser.take_diagnostics().is_empty());
+        let (diagnostics, receivers) = Checker::resolve(&ast);
+        assert!(!diagnostics.has_errors(), "{:?}", diagnostics);
+        let mut out = Vec::new();
+        CodeGen::new(&mut out, receivers).generate(ast).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // the instructions of `source`, without the tabs
+    fn instructions(source: &str) -> Vec<String> {
+        assembly(source).lines().map(|line| line.trim().to_string()).collect()
+    }
+
+    fn position(instructions: &[String], instruction: &str) -> usize {
+        instructions.iter().position(|line| line == instruction)
+            .unwrap_or_else(|| panic!("no '{}' in {:#?}", instruction, instructions))
+    }
+
+    #[test]
+    fn binary_operands_are_evaluated_left_to_right() {
+        let code = instructions("a: func -> int32 { return 1; }\nb: func -> int32 { return 2; }\nf: func -> int32 { return a() - b(); }");
+        let sub = position(&code, "sub rax, rcx");
+        assert_eq!(code[sub - 5..sub], ["call test_dust__a__", "push rax", "call test_dust__b__", "mov rcx, rax", "pop rax"]);
+    }
+
+    #[test]
+    fn the_indexed_object_is_evaluated_before_the_index() {
+        let code = instructions("f: func(a: Array<int32>, i: int32) -> int32 { return a[i]; }");
+        let load = position(&code, "mov rax, [rax + rcx * 8]");
+        assert_eq!(code[load - 5..load], ["mov rax, [rbp - 8]", "push rax", "mov rax, [rbp - 16]", "mov rcx, rax", "pop rax"]);
+    }
+
+    #[test]
+    fn every_declaration_gets_its_own_slot() {
+        let code = instructions("f: func -> int32 { val x = 1; { val x = 2; } var y = x; return y; }");
+        assert_eq!(code[1..11], [
+            "push rbp", "mov rbp, rsp", "sub rsp, 24",
+            "mov rax, 1", "mov [rbp - 8], rax ; val x",
+            "mov rax, 2", "mov [rbp - 16], rax ; val x",
+            "mov rax, [rbp - 8]", "mov [rbp - 24], rax ; var y",
+            "mov rax, [rbp - 24]"
+        ]);
+    }
+
+    #[test]
+    fn dense_switches_use_a_jump_table() {
+        let code = instructions("f: func(x: int32) -> int32 { return switch x { 1 -> 10; 2 -> 20; 3 -> 30; 4 -> 40; _ -> 0; }; }");
+        let jump = position(&code, "jmp [rcx + rax * 8]");
+        assert_eq!(code[jump - 4..jump + 3], ["sub rax, 1", "cmp rax, 3", "ja .L2", "lea rcx, [.L1]", "jmp [rcx + rax * 8]", ".L1:", "dq .L4, .L5, .L6, .L7"]);
+        assert!(!code.contains(&"cmp rax, rcx".to_string()));
+    }
+
+    #[test]
+    fn the_first_arm_for_a_value_gets_its_table_entry() {
+        let code = instructions("f: func(x: int32) -> int32 { return switch x { 0 -> 1; 1 -> 2; 0 -> 3; 2 -> 4; 3 -> 5; _ -> 6; }; }");
+        assert!(code.contains(&"dq .L4, .L5, .L7, .L8".to_string()), "{:#?}", code);
+    }
+
+    #[test]
+    fn small_or_sparse_switches_compare_one_by_one() {
+        for (source, cases) in [
+            ("f: func(x: int32) -> int32 { return switch x { 1 -> 10; 2 -> 20; 3 -> 30; _ -> 0; }; }", 3),
+            ("f: func(x: int32) -> int32 { return switch x { 1 -> 10; 20 -> 20; 300 -> 30; 4000 -> 40; _ -> 0; }; }", 4)
+        ] {
+            let code = instructions(source);
+            assert!(!code.iter().any(|line| line.starts_with("dq ")), "{:#?}", code);
+            assert_eq!(code.iter().filter(|line| *line == "cmp rax, rcx").count(), cases);
+        }
+    }
+
+    #[test]
+    fn break_and_continue_jump_to_their_loop() {
+        let code = instructions("f: func(n: int32) { outer: for i in 0..n { while i < n { if i == 2 { continue outer; } break; } } }");
+        // .L1 tests the for loop, .L2 steps it and .L3 leaves it, .L4 and .L5 do the same for the while loop
+        let continue_outer = position(&code, "je .L6") + 1;
+        assert_eq!(code[continue_outer], "jmp .L2");
+        let break_inner = position(&code, ".L7:") + 1;
+        assert_eq!(code[break_inner..break_inner + 2], ["jmp .L5", "jmp .L4"]);
+        let step = position(&code, ".L2:");
+        assert_eq!(code[step..step + 5], [".L2:", "mov rax, [rbp - 16]", "add rax, 1", "mov [rbp - 16], rax", "jmp .L1"]);
+    }
+
+    #[test]
+    fn for_loops_stop_at_the_end_of_the_range_or_array() {
+        let code = instructions("f: func(n: int32) { for i in 0..n { } }\ng: func(n: int32) { for i in 0..=n { } }");
+        assert_eq!(code.iter().filter(|line| line.starts_with("jge ")).count(), 1);
+        assert_eq!(code.iter().filter(|line| line.starts_with("jg ")).count(), 1);
+
+        let code = instructions("f: func(a: Array<int32>) { for x in a { } }");
+        let test = position(&code, "cmp rax, [rcx - 8]");
+        assert_eq!(code[test + 1..test + 4], ["jge .L3", "mov rax, [rcx + rax * 8]", "mov [rbp - 32], rax ; for x"]);
+    }
+
+    #[test]
+    fn members_of_any_receiver_use_the_checked_struct() {
+        let code = instructions(&format!("{}g: func -> int32 {{ return mk().sum() + mk().y; }}", POINT));
+        let call = position(&code, "call test_dust__P__sum__");
+        assert_eq!(code[call - 2..call + 3], ["call test_dust__mk__", "push rax", "call test_dust__P__sum__", "add rsp, 8", "push rax"]);
+        assert!(code.contains(&"mov rax, [rax + 8] ; P.y".to_string()), "{:#?}", code);
+
+        let code = instructions(&format!("{}g: func(p: P) {{ val q = p; q.y = q.x; }}", POINT));
+        assert!(code.contains(&"mov rax, [rax + 0] ; P.x".to_string()) && code.contains(&"mov [rax + 8], rcx ; P.y".to_string()), "{:#?}", code);
+    }
+
+    #[test]
+    fn fields_holding_functions_are_called_through_their_value() {
+        let code = instructions(&format!("{}g: func(p: P) -> int32 {{ return p.f(2); }}", POINT));
+        let call = position(&code, "call rax");
+        assert_eq!(code[call - 3..call + 2], ["push rax", "mov rax, [rbp - 8]", "mov rax, [rax + 16] ; P.f", "call rax", "add rsp, 8"]);
+    }
+
+    #[test]
+    fn function_values_are_called_indirectly() {
+        let code = instructions("double: func(x: int32) -> int32 { return x * 2; }\nf: func -> int32 { val g = double; return g(3); }");
+        let store = position(&code, "mov [rbp - 8], rax ; val g");
+        assert_eq!(code[store - 1], "lea rax, [test_dust__double__]");
+        let call = position(&code, "call rax");
+        assert_eq!(code[call - 3..call + 2], ["mov rax, 3", "push rax", "mov rax, [rbp - 8]", "call rax", "add rsp, 8"]);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::lexer::{LexError, Lexer, Token, TokenType};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeKind {
+    File,
+    // ( ... )
+    Parens,
+    // { ... }
+    Braces,
+    // [ ... ]
+    Brackets,
+}
+
+impl NodeKind {
+    fn from_open(token_type: &TokenType) -> Option<NodeKind> {
+        match token_type {
+            TokenType::LParen => Some(NodeKind::Parens),
+            TokenType::LBrace => Some(NodeKind::Braces),
+            TokenType::LBracket => Some(NodeKind::Brackets),
+            _ => None
+        }
+    }
+
+    fn closed_by(&self, token_type: &TokenType) -> bool {
+        matches!((self, token_type),
+            (NodeKind::Parens, TokenType::RParen) |
+            (NodeKind::Braces, TokenType::RBrace) |
+            (NodeKind::Brackets, TokenType::RBracket))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    // index into `SyntaxTree::tokens`
+    Token(usize),
+}
+
+// a delimited group, its children include the opening and closing tokens
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: NodeKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: NodeKind) -> SyntaxNode {
+        SyntaxNode {
+            kind,
+            children: Vec::new()
+        }
+    }
+}
+
+// lossless token tree: every byte of the source is either a token or trivia
+// attached to one, so printing the tree reproduces the file exactly. It only
+// groups tokens by brackets, there are no nodes for declarations, statements or
+// expressions, those exist only in the AST the parser builds from the tokens
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    source: String,
+    tokens: Vec<Token>,
+    root: SyntaxNode,
+    errors: Vec<LexError>,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: String) -> SyntaxTree {
+        Self::build(Lexer::new(source))
+    }
+
+    pub fn build(lexer: Lexer) -> SyntaxTree {
+        let source = lexer.source().to_string();
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+
+        for token in lexer {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error)
+            }
+        }
+
+        Self::from_tokens(source, tokens, errors)
+    }
+
+    pub fn from_tokens(source: String, tokens: Vec<Token>, errors: Vec<LexError>) -> SyntaxTree {
+        // groups that have been opened but not closed yet
+        let mut stack = vec![SyntaxNode::new(NodeKind::File)];
+
+        for (index, token) in tokens.iter().enumerate() {
+            let closes = stack.len() > 1 && stack.last().unwrap().kind.closed_by(&token.token_type);
+
+            if let Some(kind) = NodeKind::from_open(&token.token_type) {
+                let mut node = SyntaxNode::new(kind);
+                node.children.push(SyntaxElement::Token(index));
+                stack.push(node);
+            } else if closes {
+                stack.last_mut().unwrap().children.push(SyntaxElement::Token(index));
+                Self::close_group(&mut stack);
+            } else {
+                // unclosed groups end before Eof, unbalanced closers stay plain tokens
+                if token.token_type == TokenType::Eof {
+                    while stack.len() > 1 {
+                        Self::close_group(&mut stack);
+                    }
+                }
+                stack.last_mut().unwrap().children.push(SyntaxElement::Token(index));
+            }
+        }
+
+        while stack.len() > 1 {
+            Self::close_group(&mut stack);
+        }
+
+        SyntaxTree {
+            source,
+            tokens,
+            root: stack.pop().unwrap(),
+            errors
+        }
+    }
+
+    fn close_group(stack: &mut Vec<SyntaxNode>) {
+        let node = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(SyntaxElement::Node(node));
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    // every token in source order, including doc comments and the final Eof
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn token(&self, index: usize) -> &Token {
+        &self.tokens[index]
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    // the token as written, identifiers in `Token::value` are normalized
+    pub fn token_text(&self, token: &Token) -> &str {
+        &self.source[token.span.start..token.span.end]
+    }
+
+    fn write_node(&self, node: &SyntaxNode, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &node.children {
+            match child {
+                SyntaxElement::Node(node) => self.write_node(node, f)?,
+                SyntaxElement::Token(index) => {
+                    let token = &self.tokens[*index];
+                    for trivia in &token.leading_trivia {
+                        f.write_str(&trivia.text)?;
+                    }
+                    f.write_str(self.token_text(token))?;
+                    for trivia in &token.trailing_trivia {
+                        f.write_str(&trivia.text)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_node(&self.root, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn assert_round_trip(source: &str) {
+        let tree = SyntaxTree::parse(source.to_string());
+        assert_eq!(tree.to_string(), source);
+    }
+
+    #[test]
+    fn round_trips_every_example() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("example");
+        let mut count = 0;
+
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let tree = SyntaxTree::parse(source.clone());
+            assert_eq!(tree.to_string(), source, "{} did not round trip", path.display());
+            count += 1;
+        }
+
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn round_trips_lex_errors() {
+        assert_round_trip("f: func() € {\n\t` \"x\\q\" 12abc /* never\n");
+        assert_round_trip("\"unterminated");
+        assert_round_trip("'ab' '' x");
+        assert_round_trip("a\0b c\0");
+    }
+
+    #[test]
+    fn round_trips_unbalanced_delimiters() {
+        assert_round_trip("} ) { ( [ ");
+        assert_round_trip("foo: func( { ] }");
+    }
+
+    #[test]
+    fn round_trips_unnormalized_identifiers() {
+        assert_round_trip("e\u{301}t\u{e9} /// doc\n\r\n");
+    }
+
+    #[test]
+    fn groups_nest() {
+        let tree = SyntaxTree::parse("a(b{c[d]})".to_string());
+        let root = tree.root();
+        assert_eq!(root.kind, NodeKind::File);
+
+        let SyntaxElement::Node(parens) = &root.children[1] else { panic!("expected a group") };
+        assert_eq!(parens.kind, NodeKind::Parens);
+
+        let SyntaxElement::Node(braces) = &parens.children[2] else { panic!("expected a group") };
+        assert_eq!(braces.kind, NodeKind::Braces);
+
+        let SyntaxElement::Node(brackets) = &braces.children[2] else { panic!("expected a group") };
+        assert_eq!(brackets.kind, NodeKind::Brackets);
+        assert_eq!(brackets.children.len(), 3);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error_code::ErrorCode;
+use crate::lexer::{LexError, Span};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note")
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: ErrorCode,
+    pub message: String,
+    // where the problem is, the message may be empty
+    pub primary: Label,
+    // related places, e.g. where a conflicting definition is
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    // suggestions on how to fix the problem
+    pub helps: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: ErrorCode, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            primary: Label {
+                span,
+                message: String::new()
+            },
+            labels: Vec::new(),
+            notes: Vec::new(),
+            helps: Vec::new()
+        }
+    }
+
+    pub fn error(code: ErrorCode, message: impl Into<String>, span: Span) -> Diagnostic {
+        Self::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: ErrorCode, message: impl Into<String>, span: Span) -> Diagnostic {
+        Self::new(Severity::Warning, code, message, span)
+    }
+
+    pub fn with_primary_label(mut self, message: impl Into<String>) -> Diagnostic {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into()
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.helps.push(help.into());
+        self
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Diagnostic {
+        Diagnostic::error(ErrorCode::from_lex_error(&error.kind), error.to_string(), error.span)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}] at {}: {}", self.severity, self.code, self.primary.span, self.message)?;
+        for label in &self.labels {
+            write!(f, "\n    {}: {}", label.span, label.message)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n    note: {}", note)?;
+        }
+        for help in &self.helps {
+            write!(f, "\n    help: {}", help)?;
+        }
+
+        Ok(())
+    }
+}
+
+// every diagnostic reported during one compilation
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn extend(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics.extend(diagnostics.diagnostics);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    // source order, so reports read top to bottom
+    pub fn sort(&mut self) {
+        self.diagnostics.sort_by_key(|diagnostic| diagnostic.primary.span.start);
+    }
+}
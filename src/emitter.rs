@@ -0,0 +1,290 @@
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::lexer::Span;
+use std::io;
+use std::io::Write;
+
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorFormat {
+    // rustc style, with source snippets
+    Human,
+    // one JSON object per line, for editors and CI
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn from_name(name: &str) -> Option<ErrorFormat> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Style {
+    Severity(Severity),
+    Help,
+    Gutter,
+    Secondary,
+    Bold,
+}
+
+impl Style {
+    fn ansi(&self) -> &'static str {
+        match self {
+            Style::Severity(Severity::Error) => "\x1b[1;31m",
+            Style::Severity(Severity::Warning) => "\x1b[1;33m",
+            Style::Severity(Severity::Note) => "\x1b[1;32m",
+            Style::Help => "\x1b[1;36m",
+            Style::Gutter | Style::Secondary => "\x1b[1;34m",
+            Style::Bold => "\x1b[1m"
+        }
+    }
+}
+
+// a label resolved to the source line it starts on
+struct Underline<'a> {
+    line: u32,
+    // display columns, tabs expanded
+    start: usize,
+    width: usize,
+    primary: bool,
+    message: &'a str,
+}
+
+// writes diagnostics for one source file
+pub struct Emitter<'a> {
+    filename: &'a str,
+    source: &'a str,
+    format: ErrorFormat,
+    color: bool,
+}
+
+impl<'a> Emitter<'a> {
+    pub fn new(filename: &'a str, source: &'a str, format: ErrorFormat, color: bool) -> Emitter<'a> {
+        Emitter {
+            filename,
+            source,
+            format,
+            color
+        }
+    }
+
+    pub fn emit(&self, diagnostic: &Diagnostic, out: &mut impl Write) -> io::Result<()> {
+        match self.format {
+            ErrorFormat::Human => writeln!(out, "{}", self.render(diagnostic)),
+            ErrorFormat::Json => writeln!(out, "{}", self.to_json(diagnostic))
+        }
+    }
+
+    fn paint(&self, style: Style, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("{}{}\x1b[0m", style.ansi(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    // error[E0000]: message
+    //  --> file:line:column
+    //   |
+    // 3 |     foo bar;
+    //   |     ^^^ label
+    //   |
+    //   = note: ...
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let severity = Style::Severity(diagnostic.severity);
+        let mut underlines = Self::positioned_labels(diagnostic)
+            .map(|(label, primary)| self.underline(&label.span, primary, &label.message))
+            .collect::<Vec<_>>();
+        underlines.sort_by_key(|underline| (underline.line, underline.start));
+
+        let last_line = underlines.iter().map(|underline| underline.line).max().unwrap_or(1);
+        let gutter_width = last_line.to_string().len();
+        let blank_gutter = self.paint(Style::Gutter, &format!("{} |", " ".repeat(gutter_width)));
+
+        let mut output = format!("{}{}\n",
+            self.paint(severity, &format!("{}[{}]", diagnostic.severity, diagnostic.code)),
+            self.paint(Style::Bold, &format!(": {}", diagnostic.message)));
+        if Self::has_position(&diagnostic.primary.span) {
+            output += &format!("{}{} {}:{}\n",
+                " ".repeat(gutter_width),
+                self.paint(Style::Gutter, "-->"),
+                self.filename,
+                diagnostic.primary.span);
+        }
+        if !underlines.is_empty() {
+            output += &format!("{}\n", blank_gutter);
+        }
+
+        let mut previous_line = None;
+        for underline in &underlines {
+            if previous_line != Some(underline.line) {
+                if previous_line.is_some_and(|previous| underline.line > previous + 1) {
+                    output += &format!("{}\n", self.paint(Style::Gutter, "..."));
+                }
+
+                let number = format!("{:>width$} |", underline.line, width = gutter_width);
+                output += &format!("{} {}\n", self.paint(Style::Gutter, &number), self.line_text(underline.line));
+                previous_line = Some(underline.line);
+            }
+
+            let (marker, style) = if underline.primary { ('^', severity) } else { ('-', Style::Secondary) };
+            let mut marks = marker.to_string().repeat(underline.width);
+            if !underline.message.is_empty() {
+                marks = format!("{} {}", marks, underline.message);
+            }
+            output += &format!("{} {}{}\n", blank_gutter, " ".repeat(underline.start), self.paint(style, &marks));
+        }
+
+        if !underlines.is_empty() && (!diagnostic.notes.is_empty() || !diagnostic.helps.is_empty()) {
+            output += &format!("{}\n", blank_gutter);
+        }
+        let equals = self.paint(Style::Gutter, &format!("{} =", " ".repeat(gutter_width)));
+        for note in &diagnostic.notes {
+            output += &format!("{} {}: {}\n", equals, self.paint(Style::Bold, "note"), note);
+        }
+        for help in &diagnostic.helps {
+            output += &format!("{} {}: {}\n", equals, self.paint(Style::Help, "help"), help);
+        }
+
+        output
+    }
+
+    // spans of nodes the compiler made up have no line to show
+    fn has_position(span: &Span) -> bool {
+        span.line > 0
+    }
+
+    // the primary label and then the others, leaving out those without a position
+    fn positioned_labels(diagnostic: &Diagnostic) -> impl Iterator<Item = (&Label, bool)> {
+        std::iter::once((&diagnostic.primary, true))
+            .chain(diagnostic.labels.iter().map(|label| (label, false)))
+            .filter(|(label, _)| Self::has_position(&label.span))
+    }
+
+    fn underline<'b>(&self, span: &Span, primary: bool, message: &'b str) -> Underline<'b> {
+        let line_start = span.start - (span.byte_column as usize - 1);
+        let line_end = self.line_end(line_start);
+        let before = &self.source[line_start..span.start];
+        // spans over several lines are underlined up to the end of the first
+        let inside = &self.source[span.start..span.end.min(line_end).max(span.start)];
+
+        Underline {
+            line: span.line,
+            start: Self::display_width(before),
+            width: Self::display_width(inside).max(1),
+            primary,
+            message
+        }
+    }
+
+    fn line_end(&self, line_start: usize) -> usize {
+        self.source[line_start..].find('\n').map_or(self.source.len(), |offset| line_start + offset)
+    }
+
+    // the 1-based line without its line ending, tabs expanded
+    fn line_text(&self, line: u32) -> String {
+        let text = self.source.split('\n').nth(line as usize - 1).unwrap_or("");
+        text.trim_end_matches('\r').replace('\t', &" ".repeat(TAB_WIDTH))
+    }
+
+    fn display_width(text: &str) -> usize {
+        text.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+    }
+
+    // {"severity":"error","code":"E0000","message":"...","file":"...","spans":[...],"notes":[...],"helps":[...]}
+    pub fn to_json(&self, diagnostic: &Diagnostic) -> String {
+        let spans = Self::positioned_labels(diagnostic)
+            .map(|(label, primary)| Self::span_json(&label.span, primary, &label.message))
+            .collect::<Vec<_>>();
+
+        format!("{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"spans\":[{}],\"notes\":[{}],\"helps\":[{}]}}",
+            json_string(&diagnostic.severity.to_string()),
+            json_string(diagnostic.code.as_str()),
+            json_string(&diagnostic.message),
+            json_string(self.filename),
+            spans.join(","),
+            diagnostic.notes.iter().map(|note| json_string(note)).collect::<Vec<_>>().join(","),
+            diagnostic.helps.iter().map(|help| json_string(help)).collect::<Vec<_>>().join(","))
+    }
+
+    fn span_json(span: &Span, primary: bool, label: &str) -> String {
+        format!("{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"primary\":{},\"label\":{}}}",
+            span.start,
+            span.end,
+            span.line,
+            span.column,
+            primary,
+            if label.is_empty() { "null".to_string() } else { json_string(label) })
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            '\t' => escaped += "\\t",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_code::ErrorCode;
+
+    const SOURCE: &str = "f: func {\n\tval x = 1;\n    x = 2;\n}\n";
+
+    fn render(diagnostic: &Diagnostic) -> String {
+        Emitter::new("test.dust", SOURCE, ErrorFormat::Human, false).render(diagnostic)
+    }
+
+    #[test]
+    fn renders_snippets_with_labels() {
+        let diagnostic = Diagnostic::error(ErrorCode::AssignToImmutable, "Cannot assign twice", Span::new(26, 27, 3, 5, 5))
+            .with_label(Span::new(11, 20, 2, 2, 2), "declared with 'val'")
+            .with_help("use 'var'");
+        assert_eq!(render(&diagnostic), concat!(
+            "error[E0019]: Cannot assign twice\n",
+            " --> test.dust:3:5\n",
+            "  |\n",
+            "2 |     val x = 1;\n",
+            "  |     --------- declared with 'val'\n",
+            "3 |     x = 2;\n",
+            "  |     ^\n",
+            "  |\n",
+            "  = help: use 'var'\n"
+        ));
+    }
+
+    #[test]
+    fn spanless_diagnostics_render_without_a_snippet() {
+        let diagnostic = Diagnostic::error(ErrorCode::MismatchedTypes, "Mismatched types", Span::default())
+            .with_label(Span::default(), "somewhere")
+            .with_note("a note");
+        assert_eq!(render(&diagnostic), "error[E0022]: Mismatched types\n  = note: a note\n");
+
+        let json = Emitter::new("test.dust", SOURCE, ErrorFormat::Json, false).to_json(&diagnostic);
+        assert!(json.contains("\"spans\":[]"), "{}", json);
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        let diagnostic = Diagnostic::error(ErrorCode::UnknownCharacter, "bad \"\t\u{1}\"", Span::new(0, 1, 1, 1, 1));
+        let json = Emitter::new("test.dust", SOURCE, ErrorFormat::Json, false).to_json(&diagnostic);
+        assert!(json.starts_with("{\"severity\":\"error\",\"code\":\"E0001\",\"message\":\"bad \\\"\\t\\u0001\\\"\""), "{}", json);
+        assert!(json.contains("{\"start\":0,\"end\":1,\"line\":1,\"column\":1,\"primary\":true,\"label\":null}"), "{}", json);
+    }
+}
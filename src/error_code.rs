@@ -0,0 +1,820 @@
+use crate::lexer::LexErrorKind;
+use std::fmt;
+
+// codes are stable: once released a code keeps its number and meaning,
+// codes that are no longer emitted stay reserved
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ErrorCode {
+    UnknownCharacter,
+    UnterminatedBlockComment,
+    UnterminatedString,
+    UnterminatedChar,
+    UnknownEscape,
+    InvalidUnicodeEscape,
+    EmptyChar,
+    CharTooLong,
+    InvalidNumber,
+    UnexpectedToken,
+    KeywordAsName,
+    UnclosedFunction,
+    MissingReturnValue,
+    UnsupportedStatement,
+    ExpectedExpression,
+    ChainedComparison,
+    InvalidAssignmentTarget,
+    Redeclaration,
+    AssignToImmutable,
+    UnresolvedName,
+    IncompatibleBranches,
+    MismatchedTypes,
+    UnreachableArm,
+    NonExhaustiveSwitch,
+    BreakOutsideLoop,
+    UndeclaredLabel,
+    NotIterable,
+    UnknownMember,
+    PrivateMember,
+    MissingFields,
+    DuplicateMember,
+    UnknownStruct,
+    ArgumentCount,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 33] = [
+        ErrorCode::UnknownCharacter,
+        ErrorCode::UnterminatedBlockComment,
+        ErrorCode::UnterminatedString,
+        ErrorCode::UnterminatedChar,
+        ErrorCode::UnknownEscape,
+        ErrorCode::InvalidUnicodeEscape,
+        ErrorCode::EmptyChar,
+        ErrorCode::CharTooLong,
+        ErrorCode::InvalidNumber,
+        ErrorCode::UnexpectedToken,
+        ErrorCode::KeywordAsName,
+        ErrorCode::UnclosedFunction,
+        ErrorCode::MissingReturnValue,
+        ErrorCode::UnsupportedStatement,
+        ErrorCode::ExpectedExpression,
+        ErrorCode::ChainedComparison,
+        ErrorCode::InvalidAssignmentTarget,
+        ErrorCode::Redeclaration,
+        ErrorCode::AssignToImmutable,
+        ErrorCode::UnresolvedName,
+        ErrorCode::IncompatibleBranches,
+        ErrorCode::MismatchedTypes,
+        ErrorCode::UnreachableArm,
+        ErrorCode::NonExhaustiveSwitch,
+        ErrorCode::BreakOutsideLoop,
+        ErrorCode::UndeclaredLabel,
+        ErrorCode::NotIterable,
+        ErrorCode::UnknownMember,
+        ErrorCode::PrivateMember,
+        ErrorCode::MissingFields,
+        ErrorCode::DuplicateMember,
+        ErrorCode::UnknownStruct,
+        ErrorCode::ArgumentCount,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::UnknownCharacter => "E0001",
+            ErrorCode::UnterminatedBlockComment => "E0002",
+            ErrorCode::UnterminatedString => "E0003",
+            ErrorCode::UnterminatedChar => "E0004",
+            ErrorCode::UnknownEscape => "E0005",
+            ErrorCode::InvalidUnicodeEscape => "E0006",
+            ErrorCode::EmptyChar => "E0007",
+            ErrorCode::CharTooLong => "E0008",
+            ErrorCode::InvalidNumber => "E0009",
+            ErrorCode::UnexpectedToken => "E0010",
+            ErrorCode::KeywordAsName => "E0011",
+            ErrorCode::UnclosedFunction => "E0012",
+            ErrorCode::MissingReturnValue => "E0013",
+            ErrorCode::UnsupportedStatement => "E0014",
+            ErrorCode::ExpectedExpression => "E0015",
+            ErrorCode::ChainedComparison => "E0016",
+            ErrorCode::InvalidAssignmentTarget => "E0017",
+            ErrorCode::Redeclaration => "E0018",
+            ErrorCode::AssignToImmutable => "E0019",
+            ErrorCode::UnresolvedName => "E0020",
+            ErrorCode::IncompatibleBranches => "E0021",
+            ErrorCode::MismatchedTypes => "E0022",
+            ErrorCode::UnreachableArm => "E0023",
+            ErrorCode::NonExhaustiveSwitch => "E0024",
+            ErrorCode::BreakOutsideLoop => "E0025",
+            ErrorCode::UndeclaredLabel => "E0026",
+            ErrorCode::NotIterable => "E0027",
+            ErrorCode::UnknownMember => "E0028",
+            ErrorCode::PrivateMember => "E0029",
+            ErrorCode::MissingFields => "E0030",
+            ErrorCode::DuplicateMember => "E0031",
+            ErrorCode::UnknownStruct => "E0032",
+            ErrorCode::ArgumentCount => "E0033"
+        }
+    }
+
+    // accepts "E0012" as well as "e0012"
+    pub fn from_name(code: &str) -> Option<ErrorCode> {
+        Self::ALL.into_iter().find(|known| known.as_str().eq_ignore_ascii_case(code))
+    }
+
+    pub fn from_lex_error(kind: &LexErrorKind) -> ErrorCode {
+        match kind {
+            LexErrorKind::UnknownCharacter(_) => ErrorCode::UnknownCharacter,
+            LexErrorKind::UnterminatedBlockComment => ErrorCode::UnterminatedBlockComment,
+            LexErrorKind::UnterminatedString => ErrorCode::UnterminatedString,
+            LexErrorKind::UnterminatedChar => ErrorCode::UnterminatedChar,
+            LexErrorKind::UnknownEscape(_) => ErrorCode::UnknownEscape,
+            LexErrorKind::InvalidUnicodeEscape(_) => ErrorCode::InvalidUnicodeEscape,
+            LexErrorKind::EmptyChar => ErrorCode::EmptyChar,
+            LexErrorKind::CharTooLong => ErrorCode::CharTooLong,
+            LexErrorKind::InvalidNumber(_) => ErrorCode::InvalidNumber
+        }
+    }
+
+    // long form explanation printed by `dust explain`
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            ErrorCode::UnknownCharacter => r#"A character that is not part of Dust's syntax appeared outside of a string,
+character literal or comment.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        return 0€;
+    }
+
+Remove the character, or move it into a string or comment:
+
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::UnterminatedBlockComment => r#"A block comment was opened with `/*` but the file ended before the matching
+`*/`. Block comments nest, so every `/*` needs its own `*/`.
+
+Erroneous code example:
+
+    /* outer /* inner */
+    main: func(): int32 {
+        return 0;
+    }
+
+Close every comment that was opened:
+
+    /* outer /* inner */ */
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::UnterminatedString => r#"A string literal was opened with `"` but the file ended before the closing
+`"`. Strings may span several lines, so everything after a missing quote up to
+the end of the file becomes part of the literal. The error points at the
+opening `"`.
+
+Erroneous code example:
+
+    greeting: func(): string {
+        return "hello;
+    }
+
+    main: func(): int32 {
+        return 0;
+    }
+
+Close the string where it is meant to end:
+
+    greeting: func(): string {
+        return "hello";
+    }
+
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::UnterminatedChar => r#"A character literal was opened with `'` but never closed.
+
+Erroneous code example:
+
+    main: func(): char {
+        return 'a;
+    }
+
+Close the literal with `'`:
+
+    main: func(): char {
+        return 'a';
+    }
+"#,
+            ErrorCode::UnknownEscape => r#"A string or character literal contains a backslash escape Dust does not know.
+The supported escapes are `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\'` and
+`\u{...}`.
+
+Erroneous code example:
+
+    main: func(): string {
+        return "tab\q";
+    }
+
+Use one of the supported escapes, or `\\` for a literal backslash:
+
+    main: func(): string {
+        return "tab\t";
+    }
+"#,
+            ErrorCode::InvalidUnicodeEscape => r#"A `\u` escape is malformed. It must be written as `\u{...}` with 1 to 6
+hexadecimal digits naming a unicode scalar value, surrogates (D800 to DFFF)
+are not allowed.
+
+Erroneous code example:
+
+    main: func(): char {
+        return '\u{D800}';
+    }
+
+Use a valid scalar value:
+
+    main: func(): char {
+        return '\u{E9}';
+    }
+"#,
+            ErrorCode::EmptyChar => r#"A character literal has to contain exactly one character, `''` contains none.
+
+Erroneous code example:
+
+    main: func(): char {
+        return '';
+    }
+
+Put a character between the quotes:
+
+    main: func(): char {
+        return ' ';
+    }
+"#,
+            ErrorCode::CharTooLong => r#"A character literal contains more than one character. Use a string for text.
+
+Erroneous code example:
+
+    main: func(): char {
+        return 'ab';
+    }
+
+Use a single character, or a string:
+
+    main: func(): string {
+        return "ab";
+    }
+"#,
+            ErrorCode::InvalidNumber => r#"A number literal is malformed. This covers digits that don't fit the base
+(`0b102`), unknown suffixes (`10q`), values out of range for their suffix
+(`300u8`, or `128i8`, which is only valid as `-128i8`) and float literals in
+another base or with an integer suffix.
+
+Erroneous code example:
+
+    main: func(): uint8 {
+        return 300u8;
+    }
+
+Use a value that fits the type, or a wider suffix:
+
+    main: func(): uint16 {
+        return 300u16;
+    }
+"#,
+            ErrorCode::UnexpectedToken => r#"The parser expected one token but found another, for example a declaration
+without the `:` between its name and its type.
+
+Erroneous code example:
+
+    main func(): int32 {
+        return 0;
+    }
+
+Add the missing token:
+
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::KeywordAsName => r#"A reserved keyword was used where a name is expected. Keywords such as
+`func`, `return` or `struct` cannot name functions, arguments or types.
+
+Erroneous code example:
+
+    return: func(): int32 {
+        return 0;
+    }
+
+Pick a name that is not a keyword:
+
+    return_: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::UnclosedFunction => r#"A function body was opened with `{` but the matching `}` is missing.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        return 0;
+
+Close the body:
+
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::MissingReturnValue => r#"A function with a return type other than `void` used `return` without a
+value.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        return;
+    }
+
+Return a value of the declared type, or drop the return type:
+
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::UnsupportedStatement => r#"Note: this error code is no longer emitted by the compiler.
+
+The function body contained a statement the compiler could not parse yet,
+which was anything other than `return`.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        print(1);
+        return 0;
+    }
+
+Remove the statement:
+
+    main: func(): int32 {
+        return 0;
+    }
+"#,
+            ErrorCode::ExpectedExpression => r#"An expression was expected, for example after `return` or an operator, but
+the next token cannot start one.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        return 1 + ;
+    }
+
+Complete the expression:
+
+    main: func(): int32 {
+        return 1 + 2;
+    }
+"#,
+            ErrorCode::ChainedComparison => r#"Comparison operators (`==`, `!=`, `<`, `<=`, `>`, `>=`) do not associate,
+so `a < b < c` is rejected instead of silently comparing a boolean with `c`.
+
+Erroneous code example:
+
+    check: func(a: int32, b: int32, c: int32): bool {
+        return a < b < c;
+    }
+
+Combine the comparisons with `&&`:
+
+    check: func(a: int32, b: int32, c: int32): bool {
+        return a < b && b < c;
+    }
+"#,
+            ErrorCode::InvalidAssignmentTarget => r#"The left-hand side of an assignment is not a place that can hold a value.
+Only variables, fields (`self.x`) and indexed elements (`items[0]`) can be
+assigned to.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        1 + 2 = 3;
+        return 0;
+    }
+
+Assign to a variable, field or element instead:
+
+    set: func(items: Array<int32>): int32 {
+        items[0] = 3;
+        return 0;
+    }
+"#,
+            ErrorCode::Redeclaration => r#"A variable was declared twice in the same block. A declaration may shadow a
+variable of an enclosing block or a function argument, but not one from its
+own block.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        val x = 1;
+        val x = 2;
+        return x;
+    }
+
+Use another name, or declare the variable with `var` and assign to it:
+
+    main: func(): int32 {
+        var x = 1;
+        x = 2;
+        return x;
+    }
+"#,
+            ErrorCode::AssignToImmutable => r#"A variable declared with `val`, or a function argument, was assigned to.
+Only variables declared with `var` can change after their declaration.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        val x = 1;
+        x = 2;
+        return x;
+    }
+
+Declare the variable with `var`:
+
+    main: func(): int32 {
+        var x = 1;
+        x = 2;
+        return x;
+    }
+"#,
+            ErrorCode::UnresolvedName => r#"A name was used that is not a variable, an argument or a function in scope.
+Variables are only visible from their declaration to the end of the block
+declaring them.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        {
+            val x = 1;
+        }
+        return x;
+    }
+
+Declare the variable where it is used:
+
+    main: func(): int32 {
+        val x = 1;
+        return x;
+    }
+"#,
+            ErrorCode::IncompatibleBranches => r#"The branches of an `if` or the arms of an `if x == { ... }` whose value is
+used produce values of different types. An `if` used as a value also needs an
+`else` branch, since without one there is nothing to produce when the
+condition is false.
+
+Erroneous code example:
+
+    main: func(a: int32): int32 {
+        val x = if a > 0 { 1 } else { "negative" };
+        return x;
+    }
+
+Make every branch produce the same type:
+
+    main: func(a: int32): int32 {
+        val x = if a > 0 { 1 } else { -1 };
+        return x;
+    }
+"#,
+            ErrorCode::MismatchedTypes => r#"A value has a different type than the one it is declared or compared with.
+Integer and float literals without a suffix take the type they are used as.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        val x: int32 = "one";
+        return x;
+    }
+
+Use a value of the declared type:
+
+    main: func(): int32 {
+        val x: int32 = 1;
+        return x;
+    }
+"#,
+            ErrorCode::UnreachableArm => r#"A `switch` arm can never be taken, because an earlier arm is the wildcard
+`_` or already matches the same value. This is a warning, the arm is ignored.
+
+Erroneous code example:
+
+    main: func(a: int32): int32 {
+        return switch a {
+            1 -> 10;
+            _ -> 0;
+            2 -> 20;
+        };
+    }
+
+Move the wildcard arm last:
+
+    main: func(a: int32): int32 {
+        return switch a {
+            1 -> 10;
+            2 -> 20;
+            _ -> 0;
+        };
+    }
+"#,
+            ErrorCode::NonExhaustiveSwitch => r#"A `switch` whose value is used has no wildcard arm `_`, so there is no
+value to produce when none of the patterns match. The same goes for the
+`if a == { ... }` form.
+
+Erroneous code example:
+
+    main: func(a: int32): int32 {
+        return switch a {
+            1 -> 10;
+            2 -> 20;
+        };
+    }
+
+Add a wildcard arm:
+
+    main: func(a: int32): int32 {
+        return switch a {
+            1 -> 10;
+            2 -> 20;
+            _ -> 0;
+        };
+    }
+"#,
+            ErrorCode::BreakOutsideLoop => r#"`break` or `continue` was used outside of a `while` or `for` loop, where
+there is no loop to leave or to continue.
+
+Erroneous code example:
+
+    main: func(a: int32): int32 {
+        if a > 0 {
+            break;
+        }
+        return a;
+    }
+
+Use `return` to leave a function early:
+
+    main: func(a: int32): int32 {
+        if a > 0 {
+            return 0;
+        }
+        return a;
+    }
+"#,
+            ErrorCode::UndeclaredLabel => r#"`break` or `continue` names a label that no enclosing loop has. A loop is
+labeled by writing a name and `:` before `while` or `for`.
+
+Erroneous code example:
+
+    main: func(n: int32): int32 {
+        for i in 0..n {
+            for j in 0..n {
+                break outer;
+            }
+        }
+        return 0;
+    }
+
+Label the loop to leave:
+
+    main: func(n: int32): int32 {
+        outer: for i in 0..n {
+            for j in 0..n {
+                break outer;
+            }
+        }
+        return 0;
+    }
+"#,
+            ErrorCode::NotIterable => r#"A `for` loop can go over a range (`0..n` or `0..=n`) or an `Array<T>`, but
+was given a value of another type.
+
+Erroneous code example:
+
+    main: func(n: int32): int32 {
+        for i in n {
+        }
+        return 0;
+    }
+
+Loop over a range instead:
+
+    main: func(n: int32): int32 {
+        for i in 0..n {
+        }
+        return 0;
+    }
+"#,
+            ErrorCode::UnknownMember => r#"A field or method was used that the struct does not declare, or a struct
+without a constructor was called like a function. Values that are not structs
+have no members at all, and a method can only be called, not read like a
+field.
+
+Erroneous code example:
+
+    point: struct {
+        pub x: int32;
+    }
+
+    main: func(p: point): int32 {
+        return p.y;
+    }
+
+Use a member the struct declares:
+
+    point: struct {
+        pub x: int32;
+    }
+
+    main: func(p: point): int32 {
+        return p.x;
+    }
+"#,
+            ErrorCode::PrivateMember => r#"A field, method or constructor that is not `pub` was used outside of the
+struct's own methods. Members are `proct` (protected) unless marked `pub` or
+`priv`.
+
+Erroneous code example:
+
+    point: struct {
+        priv x: int32;
+    }
+
+    main: func(p: point): int32 {
+        return p.x;
+    }
+
+Mark the member `pub`, or only use it inside the struct's methods:
+
+    point: struct {
+        pub x: int32;
+    }
+
+    main: func(p: point): int32 {
+        return p.x;
+    }
+"#,
+            ErrorCode::MissingFields => r#"A struct literal has to give every field of the struct a value.
+
+Erroneous code example:
+
+    point: struct {
+        pub x: int32;
+        pub y: int32;
+    }
+
+    main: func(): int32 {
+        val p = point { x: 1 };
+        return p.x;
+    }
+
+Give the missing fields a value:
+
+    point: struct {
+        pub x: int32;
+        pub y: int32;
+    }
+
+    main: func(): int32 {
+        val p = point { x: 1, y: 2 };
+        return p.x;
+    }
+"#,
+            ErrorCode::DuplicateMember => r#"A struct declares two fields or methods with the same name, or more than
+one constructor.
+
+Erroneous code example:
+
+    point: struct {
+        pub x: int32;
+        pub x: float32;
+    }
+
+Give every member its own name:
+
+    point: struct {
+        pub x: int32;
+        pub y: float32;
+    }
+"#,
+            ErrorCode::UnknownStruct => r#"A struct literal names a struct that is not declared in the file.
+
+Erroneous code example:
+
+    main: func(): int32 {
+        val p = point { x: 1 };
+        return 0;
+    }
+
+Declare the struct:
+
+    point: struct {
+        pub x: int32;
+    }
+
+    main: func(): int32 {
+        val p = point { x: 1 };
+        return 0;
+    }
+"#,
+            ErrorCode::ArgumentCount => r#"A function, or a value of a function type, was called with a different
+number of arguments than it has parameters.
+
+Erroneous code example:
+
+    add: func(a: int32, b: int32): int32 {
+        return a + b;
+    }
+
+    main: func(): int32 {
+        return add(1);
+    }
+
+Pass one argument per parameter:
+
+    add: func(a: int32, b: int32): int32 {
+        return a + b;
+    }
+
+    main: func(): int32 {
+        return add(1, 2);
+    }
+"#
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    // the indented code blocks of an explanation
+    fn examples(explanation: &str) -> Vec<String> {
+        let mut examples = Vec::new();
+        let mut current: Option<String> = None;
+        for line in explanation.lines() {
+            match (line.strip_prefix("    "), &mut current) {
+                (Some(code), Some(example)) => example.push_str(&format!("{}\n", code)),
+                (Some(code), None) => current = Some(format!("{}\n", code)),
+                (None, Some(example)) if line.is_empty() => example.push('\n'),
+                (None, _) => examples.extend(current.take())
+            }
+        }
+        examples.extend(current);
+        examples
+    }
+
+    // the codes of every diagnostic for `source`, as main reports them
+    fn codes(source: &str) -> Vec<ErrorCode> {
+        let mut parser = Parser::new(Lexer::new(source.to_string()));
+        let ast = parser.parse("example.dust".to_string());
+        let mut diagnostics = parser.take_diagnostics();
+        if !diagnostics.has_errors() {
+            diagnostics.extend(Checker::check(&ast));
+        }
+        diagnostics.iter().map(|diagnostic| diagnostic.code).collect()
+    }
+
+    #[test]
+    fn codes_are_unique_and_round_trip() {
+        for (index, code) in ErrorCode::ALL.into_iter().enumerate() {
+            assert_eq!(code.as_str(), format!("E{:04}", index + 1));
+            assert_eq!(ErrorCode::from_name(code.as_str()), Some(code));
+            assert_eq!(ErrorCode::from_name(&code.as_str().to_lowercase()), Some(code));
+        }
+        assert_eq!(ErrorCode::from_name("E9999"), None);
+    }
+
+    #[test]
+    fn explanation_examples_behave_as_described() {
+        for code in ErrorCode::ALL {
+            if code.explanation().contains("no longer emitted") {
+                continue;
+            }
+
+            let examples = examples(code.explanation());
+            assert!(examples.len() >= 2, "{} needs an erroneous and a fixed example", code);
+            assert!(codes(&examples[0]).contains(&code), "the example of {} doesn't report it:\n{}", code, examples[0]);
+            assert_eq!(codes(&examples[1]), vec![], "the fixed example of {} isn't clean:\n{}", code, examples[1]);
+        }
+    }
+}
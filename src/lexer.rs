@@ -1,44 +1,537 @@
+use crate::symbol::Symbol;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use unicode_xid::UnicodeXID;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
     LAngle,
     RAngle,
     Colon,
     Identifier,
+    Keyword(Keyword),
     Semicolon,
     Number,
-    EOF
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Equal,
+    EqualEqual,
+    Bang,
+    BangEqual,
+    LessEqual,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
+    ShiftLeftEqual,
+    ShiftRightEqual,
+    Arrow,
+    Dot,
+    DotDot,
+    DotDotEqual,
+    Question,
+    Dollar,
+    Hash,
+    At,
+    Underscore,
+    String,
+    Char,
+    DocComment,
+    Eof
+}
+
+// reserved words, see example/Keywords.txt
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Keyword {
+    Proct,
+    Pub,
+    Priv,
+    Refcounted,
+    Func,
+    Return,
+    Struct,
+    Val,
+    Var,
+    If,
+    Else,
+    Switch,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    SelfValue
+}
+
+impl Keyword {
+    pub fn from_word(word: &str) -> Option<Keyword> {
+        match word {
+            "proct" => Some(Keyword::Proct),
+            "pub" => Some(Keyword::Pub),
+            "priv" => Some(Keyword::Priv),
+            "refcounted" => Some(Keyword::Refcounted),
+            "func" => Some(Keyword::Func),
+            "return" => Some(Keyword::Return),
+            "struct" => Some(Keyword::Struct),
+            "val" => Some(Keyword::Val),
+            "var" => Some(Keyword::Var),
+            "if" => Some(Keyword::If),
+            "else" => Some(Keyword::Else),
+            "switch" => Some(Keyword::Switch),
+            "while" => Some(Keyword::While),
+            "for" => Some(Keyword::For),
+            "in" => Some(Keyword::In),
+            "break" => Some(Keyword::Break),
+            "continue" => Some(Keyword::Continue),
+            "self" => Some(Keyword::SelfValue),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Keyword::Proct => "proct",
+            Keyword::Pub => "pub",
+            Keyword::Priv => "priv",
+            Keyword::Refcounted => "refcounted",
+            Keyword::Func => "func",
+            Keyword::Return => "return",
+            Keyword::Struct => "struct",
+            Keyword::Val => "val",
+            Keyword::Var => "var",
+            Keyword::If => "if",
+            Keyword::Else => "else",
+            Keyword::Switch => "switch",
+            Keyword::While => "while",
+            Keyword::For => "for",
+            Keyword::In => "in",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::SelfValue => "self"
+        }
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// byte offsets into the source plus the 1-based line/column of the start,
+// `column` counts unicode scalar values and `byte_column` UTF-8 bytes
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+    pub byte_column: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: u32, column: u32, byte_column: u32) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+            byte_column
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // span covering both self and other, positioned at self
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            line: self.line,
+            column: self.column,
+            byte_column: self.byte_column
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+    // text the lexer reported an error for
+    Skipped
+}
+
+// source text between tokens that the parser skips but tooling may need
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span
+}
+
+// the decoded value of a literal token
+#[derive(Debug, PartialEq, Clone)]
+pub enum Literal {
+    Int { value: u128, suffix: Option<NumberSuffix> },
+    Float { value: f64, suffix: Option<NumberSuffix> },
+    Str(String),
+    Char(char)
+}
+
+// explicit type of a numeric literal, e.g. `10u8` or `1.5f32`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NumberSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64
+}
+
+impl NumberSuffix {
+    pub fn from_suffix(suffix: &str) -> Option<NumberSuffix> {
+        match suffix {
+            "i8" => Some(NumberSuffix::I8),
+            "i16" => Some(NumberSuffix::I16),
+            "i32" => Some(NumberSuffix::I32),
+            "i64" => Some(NumberSuffix::I64),
+            "u8" => Some(NumberSuffix::U8),
+            "u16" => Some(NumberSuffix::U16),
+            "u32" => Some(NumberSuffix::U32),
+            "u64" => Some(NumberSuffix::U64),
+            "f32" => Some(NumberSuffix::F32),
+            "f64" => Some(NumberSuffix::F64),
+            _ => None
+        }
+    }
+
+    // the name of the type in Types.txt
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            NumberSuffix::I8 => "int8",
+            NumberSuffix::I16 => "int16",
+            NumberSuffix::I32 => "int32",
+            NumberSuffix::I64 => "int64",
+            NumberSuffix::U8 => "uint8",
+            NumberSuffix::U16 => "uint16",
+            NumberSuffix::U32 => "uint32",
+            NumberSuffix::U64 => "uint64",
+            NumberSuffix::F32 => "float32",
+            NumberSuffix::F64 => "float64"
+        }
+    }
+
+    pub fn type_symbol(&self) -> Symbol {
+        match self {
+            NumberSuffix::I8 => Symbol::INT8,
+            NumberSuffix::I16 => Symbol::INT16,
+            NumberSuffix::I32 => Symbol::INT32,
+            NumberSuffix::I64 => Symbol::INT64,
+            NumberSuffix::U8 => Symbol::UINT8,
+            NumberSuffix::U16 => Symbol::UINT16,
+            NumberSuffix::U32 => Symbol::UINT32,
+            NumberSuffix::U64 => Symbol::UINT64,
+            NumberSuffix::F32 => Symbol::FLOAT32,
+            NumberSuffix::F64 => Symbol::FLOAT64
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, NumberSuffix::F32 | NumberSuffix::F64)
+    }
+
+    // largest value an integer literal of this type may have, signed types
+    // allow their minimum's magnitude so that `-128i8` can be written
+    fn max_value(&self) -> u128 {
+        match self {
+            NumberSuffix::I8 => 1 << 7,
+            NumberSuffix::I16 => 1 << 15,
+            NumberSuffix::I32 => 1 << 31,
+            NumberSuffix::I64 => 1 << 63,
+            NumberSuffix::U8 => u8::MAX as u128,
+            NumberSuffix::U16 => u16::MAX as u128,
+            NumberSuffix::U32 => u32::MAX as u128,
+            NumberSuffix::U64 | NumberSuffix::F32 | NumberSuffix::F64 => u64::MAX as u128
+        }
+    }
+
+    // largest value of this type, a larger signed literal is only valid negated
+    pub fn max_positive(&self) -> u128 {
+        match self {
+            NumberSuffix::I8 => i8::MAX as u128,
+            NumberSuffix::I16 => i16::MAX as u128,
+            NumberSuffix::I32 => i32::MAX as u128,
+            NumberSuffix::I64 => i64::MAX as u128,
+            suffix => suffix.max_value()
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexErrorKind {
+    UnknownCharacter(char),
+    UnterminatedBlockComment,
+    UnterminatedString,
+    UnterminatedChar,
+    UnknownEscape(char),
+    InvalidUnicodeEscape(String),
+    EmptyChar,
+    CharTooLong,
+    InvalidNumber(String)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span
+}
+
+impl LexError {
+    pub fn new(kind: LexErrorKind, span: Span) -> LexError {
+        LexError {
+            kind,
+            span
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::UnknownCharacter(c) => write!(f, "unknown character '{}'", c.escape_debug()),
+            LexErrorKind::UnterminatedBlockComment => write!(f, "unterminated block comment"),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::UnterminatedChar => write!(f, "unterminated character literal"),
+            LexErrorKind::UnknownEscape(c) => write!(f, "unknown escape sequence '\\{}'", c),
+            LexErrorKind::InvalidUnicodeEscape(reason) => write!(f, "invalid unicode escape: {}", reason),
+            LexErrorKind::EmptyChar => write!(f, "empty character literal"),
+            LexErrorKind::CharTooLong => write!(f, "character literal may only contain one character"),
+            LexErrorKind::InvalidNumber(reason) => write!(f, "invalid number literal: {}", reason)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub value: String,
-    pub char_pos: i32,
-    pub line: i32
+    // the name of an identifier or keyword, Symbol::EMPTY for every other token
+    // so that literals don't fill up the interner
+    pub value: Symbol,
+    pub span: Span,
+    pub literal: Option<Literal>,
+    // trivia since the previous token's trailing trivia
+    pub leading_trivia: Vec<Trivia>,
+    // trivia after the token up to (not including) the end of its line
+    pub trailing_trivia: Vec<Trivia>
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, value: String, char_pos: i32, line: i32) -> Token {
+    pub fn new(token_type: TokenType, value: Symbol, span: Span) -> Token {
         Token {
             token_type,
             value,
-            char_pos,
-            line
+            span,
+            literal: None,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new()
         }
     }
 }
 
+// longest operators first so that lexing is maximal munch
+const OPERATORS: &[(&str, TokenType)] = &[
+    ("<<=", TokenType::ShiftLeftEqual),
+    (">>=", TokenType::ShiftRightEqual),
+    ("..=", TokenType::DotDotEqual),
+    ("==", TokenType::EqualEqual),
+    ("!=", TokenType::BangEqual),
+    ("<=", TokenType::LessEqual),
+    (">=", TokenType::GreaterEqual),
+    ("&&", TokenType::AndAnd),
+    ("||", TokenType::OrOr),
+    ("<<", TokenType::ShiftLeft),
+    (">>", TokenType::ShiftRight),
+    ("+=", TokenType::PlusEqual),
+    ("-=", TokenType::MinusEqual),
+    ("*=", TokenType::StarEqual),
+    ("/=", TokenType::SlashEqual),
+    ("%=", TokenType::PercentEqual),
+    ("&=", TokenType::AmpersandEqual),
+    ("|=", TokenType::PipeEqual),
+    ("^=", TokenType::CaretEqual),
+    ("->", TokenType::Arrow),
+    ("..", TokenType::DotDot),
+    ("(", TokenType::LParen),
+    (")", TokenType::RParen),
+    ("{", TokenType::LBrace),
+    ("}", TokenType::RBrace),
+    ("[", TokenType::LBracket),
+    ("]", TokenType::RBracket),
+    (",", TokenType::Comma),
+    ("<", TokenType::LAngle),
+    (">", TokenType::RAngle),
+    (":", TokenType::Colon),
+    (";", TokenType::Semicolon),
+    ("+", TokenType::Plus),
+    ("-", TokenType::Minus),
+    ("*", TokenType::Star),
+    ("/", TokenType::Slash),
+    ("%", TokenType::Percent),
+    ("=", TokenType::Equal),
+    ("!", TokenType::Bang),
+    ("&", TokenType::Ampersand),
+    ("|", TokenType::Pipe),
+    ("^", TokenType::Caret),
+    ("~", TokenType::Tilde),
+    (".", TokenType::Dot),
+    ("?", TokenType::Question),
+    ("$", TokenType::Dollar),
+    ("#", TokenType::Hash),
+    ("@", TokenType::At),
+];
+
+// replaces the byte range `range` of a source with `text`
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: String) -> TextEdit {
+        TextEdit {
+            range,
+            text
+        }
+    }
+
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = String::with_capacity(source.len() + self.text.len());
+        edited.push_str(&source[..self.range.start]);
+        edited.push_str(&self.text);
+        edited.push_str(&source[self.range.end..]);
+        edited
+    }
+
+    // how much the edit moves the text after it
+    fn delta(&self) -> isize {
+        self.text.len() as isize - self.range.len() as isize
+    }
+}
+
+impl Token {
+    // span of the token together with its trivia
+    pub fn full_span(&self) -> Span {
+        let start = self.leading_trivia.first().map_or(self.span, |trivia| trivia.span);
+        let end = self.trailing_trivia.last().map_or(self.span, |trivia| trivia.span);
+        start.to(end)
+    }
+
+    fn shift(&mut self, shift: &Shift) {
+        self.span = shift.apply(self.span);
+        for trivia in self.leading_trivia.iter_mut().chain(self.trailing_trivia.iter_mut()) {
+            trivia.span = shift.apply(trivia.span);
+        }
+    }
+}
+
+// moves spans that come after an edit to where that text is now
+struct Shift {
+    offset: isize,
+    lines: i32,
+    // the line the unchanged text starts on, before the edit
+    line: u32,
+    columns: i32,
+    byte_columns: i32
+}
+
+impl Shift {
+    fn apply(&self, span: Span) -> Span {
+        let mut shifted = span;
+        shifted.start = span.start.wrapping_add_signed(self.offset);
+        shifted.end = span.end.wrapping_add_signed(self.offset);
+        shifted.line = span.line.wrapping_add_signed(self.lines);
+
+        // only text on the line the edit ended on moves sideways
+        if span.line == self.line {
+            shifted.column = span.column.wrapping_add_signed(self.columns);
+            shifted.byte_column = span.byte_column.wrapping_add_signed(self.byte_columns);
+        }
+
+        shifted
+    }
+}
+
+// where a token or trivia started
+#[derive(Debug, Clone, Copy)]
+struct Mark {
+    pos: usize,
+    line: u32,
+    column: u32,
+    byte_column: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct Lexer {
     source: String,
+    // byte offset of the next character
     pos: usize,
-    char_pos: i32,
-    line: i32,
+    line: u32,
+    column: u32,
+    // byte offset of the first character of the current line
+    line_start: usize,
+    // tokens lexed by `peek_nth` but not yet returned
+    lookahead: VecDeque<Result<Token, LexError>>,
+    // trivia (and skipped text) waiting for the next token
+    pending_trivia: Vec<Trivia>,
+    // error found while lexing trivia, reported before the next token
+    pending_error: Option<LexError>,
+    // set once the Eof token has been produced
+    finished: bool,
 }
 
 impl Lexer {
@@ -46,281 +539,977 @@ impl Lexer {
         Lexer {
             source,
             pos: 0,
-            char_pos: 1,
-            line: 1
+            line: 1,
+            column: 1,
+            line_start: 0,
+            lookahead: VecDeque::new(),
+            pending_trivia: Vec::new(),
+            pending_error: None,
+            finished: false
         }
     }
 
-    fn peek_char(&self) -> char {
-        self.source.chars().nth(self.pos + 1).unwrap()
+    // continues lexing `source` from `start`, which has to be a token boundary
+    fn resume(source: String, start: Span) -> Lexer {
+        let mut lexer = Lexer::new(source);
+        lexer.pos = start.start;
+        lexer.line = start.line;
+        lexer.column = start.column;
+        lexer.line_start = start.start + 1 - start.byte_column as usize;
+        lexer
     }
 
-    pub fn try_token(&mut self) -> Option<Token> {
-        let c = if let Some(ch) = self.source.chars().nth(self.pos) {
-            ch
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn current_char(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        let mut chars = self.source[self.pos..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.current_char()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+            self.line_start = self.pos;
         } else {
-            return Some(Token::new(TokenType::EOF, "".to_string(), self.char_pos, self.line));
-        };
-        self.pos += 1;
+            self.column += 1;
+        }
 
-        match c {
-            '(' => {
-                return Some(Token::new(TokenType::LParen, c.to_string(), self.char_pos, self.line));
+        Some(c)
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            pos: self.pos,
+            line: self.line,
+            column: self.column,
+            byte_column: (self.pos - self.line_start) as u32 + 1
+        }
+    }
+
+    fn make_token(&self, token_type: TokenType, start: Mark) -> Token {
+        Token::new(token_type, Symbol::EMPTY, self.span_from(start))
+    }
+
+    fn span_from(&self, start: Mark) -> Span {
+        Span::new(start.pos, self.pos, start.line, start.column, start.byte_column)
+    }
+
+    fn starts_with(&self, pattern: &str) -> bool {
+        self.source[self.pos..].starts_with(pattern)
+    }
+
+    fn is_doc_comment(&self) -> bool {
+        self.starts_with("///") && !self.starts_with("////")
+    }
+
+    // lexes one piece of trivia, stopping before a newline if `same_line` is set
+    fn try_trivia(&mut self, same_line: bool) -> Option<Trivia> {
+        let start = self.mark();
+
+        let c = self.current_char()?;
+        let kind = if c.is_whitespace() {
+            if same_line && c == '\n' {
+                return None;
             }
-            ')' => {
-                return Some(Token::new(TokenType::RParen, c.to_string(), self.char_pos, self.line));
+            while let Some(next) = self.current_char() {
+                if !next.is_whitespace() || (same_line && next == '\n') {
+                    break;
+                }
+                self.bump();
             }
-            '{' => {
-                return Some(Token::new(TokenType::LBrace, c.to_string(), self.char_pos, self.line));
+            TriviaKind::Whitespace
+        } else if self.starts_with("//") && !self.is_doc_comment() {
+            while let Some(next) = self.current_char() {
+                if next == '\n' {
+                    break;
+                }
+                self.bump();
             }
-            '}' => {
-                return Some(Token::new(TokenType::RBrace, c.to_string(), self.char_pos, self.line));
+            TriviaKind::LineComment
+        } else if self.starts_with("/*") {
+            self.bump();
+            self.bump();
+
+            // block comments nest, an unterminated one runs to the end of the file
+            let mut depth = 1;
+            while depth > 0 && self.current_char().is_some() {
+                if self.starts_with("/*") {
+                    self.bump();
+                    depth += 1;
+                } else if self.starts_with("*/") {
+                    self.bump();
+                    depth -= 1;
+                }
+                self.bump();
             }
-            ',' => {
-                return Some(Token::new(TokenType::Comma, c.to_string(), self.char_pos, self.line));
+
+            if depth > 0 {
+                let error = LexError::new(LexErrorKind::UnterminatedBlockComment, self.span_from(start));
+                self.pending_error.get_or_insert(error);
             }
-            '<' => {
-                return Some(Token::new(TokenType::LAngle, c.to_string(), self.char_pos, self.line));
+            TriviaKind::BlockComment
+        } else {
+            return None;
+        };
+
+        Some(Trivia {
+            kind,
+            text: self.source[start.pos..self.pos].to_string(),
+            span: self.span_from(start)
+        })
+    }
+
+    // the opening quote has already been consumed
+    fn lex_string(&mut self, start: Mark) -> Result<Token, LexError> {
+        let mut value = String::new();
+        let mut error = None;
+
+        loop {
+            match self.bump() {
+                None => {
+                    return Err(LexError::new(LexErrorKind::UnterminatedString, self.span_from(start)));
+                }
+                Some('"') => break,
+                Some('\\') => match self.lex_escape() {
+                    Ok(c) => value.push(c),
+                    Err(err) => {
+                        // keep scanning so the rest of the string is not lexed as code
+                        error.get_or_insert(err);
+                    }
+                },
+                Some(c) => value.push(c)
             }
-            '>' => {
-                return Some(Token::new(TokenType::RAngle, c.to_string(), self.char_pos, self.line));
+        }
+
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        let mut token = self.make_token(TokenType::String, start);
+        token.literal = Some(Literal::Str(value));
+        Ok(token)
+    }
+
+    // r"..." or r#"..."#, the `r` has already been consumed
+    fn lex_raw_string(&mut self, start: Mark) -> Result<Token, LexError> {
+        let mut hashes = 0;
+        while self.current_char() == Some('#') {
+            self.bump();
+            hashes += 1;
+        }
+        self.bump();
+
+        let closing = format!("\"{}", "#".repeat(hashes));
+        let content_start = self.pos;
+        while !self.starts_with(&closing) {
+            if self.bump().is_none() {
+                return Err(LexError::new(LexErrorKind::UnterminatedString, self.span_from(start)));
             }
-            ':' => {
-                return Some(Token::new(TokenType::Colon, c.to_string(), self.char_pos, self.line));
+        }
+        let value = self.source[content_start..self.pos].to_string();
+        for _ in 0..closing.len() {
+            self.bump();
+        }
+
+        let mut token = self.make_token(TokenType::String, start);
+        token.literal = Some(Literal::Str(value));
+        Ok(token)
+    }
+
+    fn is_raw_string_start(&self) -> bool {
+        self.source[self.pos..].trim_start_matches('#').starts_with('"')
+    }
+
+    // the opening quote has already been consumed
+    fn lex_char(&mut self, start: Mark) -> Result<Token, LexError> {
+        let value = match self.bump() {
+            None | Some('\n') => {
+                return Err(LexError::new(LexErrorKind::UnterminatedChar, self.span_from(start)));
             }
-            ';' => {
-                return Some(Token::new(TokenType::Semicolon, c.to_string(), self.char_pos, self.line));
+            Some('\'') => {
+                return Err(LexError::new(LexErrorKind::EmptyChar, self.span_from(start)));
             }
-            '0'..='9' => {
-                let mut value = c.to_string();
+            Some('\\') => self.lex_escape(),
+            Some(c) => Ok(c)
+        };
 
-                let current = self.source.chars().nth(self.pos).unwrap();
-                if current.is_digit(10) {
-                    value.push(current);
+        if self.current_char() != Some('\'') {
+            // find out whether this is a long literal or a missing quote
+            let rest = &self.source[self.pos..];
+            let line_end = rest.find('\n').unwrap_or(rest.len());
+            return match rest[..line_end].find('\'') {
+                Some(quote) => {
+                    let end = self.pos + quote + 1;
+                    while self.pos < end {
+                        self.bump();
+                    }
+                    Err(LexError::new(LexErrorKind::CharTooLong, self.span_from(start)))
                 }
+                None => Err(LexError::new(LexErrorKind::UnterminatedChar, self.span_from(start)))
+            };
+        }
+        self.bump();
 
+        let mut token = self.make_token(TokenType::Char, start);
+        token.literal = Some(Literal::Char(value?));
+        Ok(token)
+    }
+
+    // the backslash has already been consumed
+    fn lex_escape(&mut self) -> Result<char, LexError> {
+        // the backslash is a single byte
+        let mut start = self.mark();
+        start.pos -= 1;
+        start.column -= 1;
+        start.byte_column -= 1;
 
-                self.pos += 1;
-                let mut next = self.source.chars().nth(self.pos).unwrap();
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Err(LexError::new(LexErrorKind::UnterminatedString, self.span_from(start)))
+        };
 
-                while next.is_digit(10) || (next == '_' && self.peek_char().is_digit(10)) {
-                    value.push(next);
-                    self.pos += 1;
-                    next = self.source.chars().nth(self.pos).unwrap();
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
+            'u' => {
+                if self.current_char() != Some('{') {
+                    return Err(LexError::new(LexErrorKind::InvalidUnicodeEscape("expected '{' after '\\u'".to_string()), self.span_from(start)));
                 }
+                self.bump();
 
-                return Some(Token {
-                    token_type: TokenType::Number,
-                    value,
-                    char_pos: self.char_pos,
-                    line: self.line
-                });
+                let digits_start = self.pos;
+                while self.current_char().is_some_and(|c| c.is_ascii_hexdigit()) {
+                    self.bump();
+                }
+                let digits = self.source[digits_start..self.pos].to_string();
 
-            }
-            'a'..='z' | 'A'..='Z' | '_' => {
-                let mut value = c.to_string();
+                if self.current_char() != Some('}') {
+                    return Err(LexError::new(LexErrorKind::InvalidUnicodeEscape("expected '}' to close the escape".to_string()), self.span_from(start)));
+                }
+                self.bump();
 
-                let current = self.source.chars().nth(self.pos).unwrap();
-                if current.is_alphanumeric() || current == '_' {
-                    value.push(current);
+                if digits.is_empty() || digits.len() > 6 {
+                    return Err(LexError::new(LexErrorKind::InvalidUnicodeEscape("expected 1 to 6 hex digits".to_string()), self.span_from(start)));
                 }
 
-                self.pos += 1;
+                u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32).ok_or_else(|| {
+                    LexError::new(LexErrorKind::InvalidUnicodeEscape(format!("'{}' is not a unicode scalar value", digits)), self.span_from(start))
+                })
+            }
+            _ => Err(LexError::new(LexErrorKind::UnknownEscape(c), self.span_from(start)))
+        }
+    }
+
+    fn eat_digits(&mut self) {
+        while self.current_char().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.bump();
+        }
+    }
+
+    // the first digit has already been consumed
+    fn lex_number(&mut self, first: char, start: Mark) -> Result<Token, LexError> {
+        let radix = match (first, self.current_char()) {
+            ('0', Some('x')) => 16,
+            ('0', Some('b')) => 2,
+            ('0', Some('o')) => 8,
+            _ => 10
+        };
+
+        let mut is_float = false;
+        let digits_start = if radix == 10 {
+            self.eat_digits();
 
-                let mut next = self.source.chars().nth(self.pos).unwrap();
-                while next.is_alphanumeric() || next == '_' {
-                    value.push(next);
-                    self.pos += 1;
-                    next = self.source.chars().nth(self.pos).unwrap();
+            // `1..2` is a range and `1.foo` a field access, not floats
+            if self.current_char() == Some('.') && self.peek_char().is_some_and(|c| c.is_ascii_digit()) {
+                is_float = true;
+                self.bump();
+                self.eat_digits();
+            }
+
+            if matches!(self.current_char(), Some('e' | 'E')) {
+                let rest = &self.source[self.pos + 1..];
+                let exponent = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+                if exponent.starts_with(|c: char| c.is_ascii_digit()) {
+                    is_float = true;
+                    self.bump();
+                    if matches!(self.current_char(), Some('+' | '-')) {
+                        self.bump();
+                    }
+                    self.eat_digits();
                 }
+            }
 
-                return Some(Token {
-                    token_type: TokenType::Identifier,
-                    value,
-                    char_pos: self.char_pos,
-                    line: self.line
-                });
+            start.pos
+        } else {
+            self.bump();
+            let digits_start = self.pos;
+            while self.current_char().is_some_and(|c| c.is_ascii_hexdigit() || c == '_') {
+                self.bump();
             }
-            '\0' => {
-                return Some(Token {
-                    token_type: TokenType::EOF,
-                    value: "".to_string(),
-                    char_pos: self.char_pos,
-                    line: self.line
-                });
+            digits_start
+        };
+        let digits_end = self.pos;
+
+        while self.current_char().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.bump();
+        }
+
+        let span = self.span_from(start);
+        let invalid = |reason: String| Err(LexError::new(LexErrorKind::InvalidNumber(reason), span));
+
+        let digits: String = self.source[digits_start..digits_end].chars().filter(|c| *c != '_').collect();
+        let suffix_text = &self.source[digits_end..self.pos];
+
+        let suffix = if suffix_text.is_empty() {
+            None
+        } else if let Some(suffix) = NumberSuffix::from_suffix(suffix_text) {
+            Some(suffix)
+        } else {
+            return invalid(format!("invalid suffix '{}'", suffix_text));
+        };
+
+        if digits.is_empty() {
+            return invalid(format!("no digits after '{}'", &self.source[start.pos..digits_start]));
+        }
+        if let Some(bad) = digits.chars().find(|c| !is_float && !c.is_digit(radix)) {
+            return invalid(format!("invalid digit '{}' for a base {} literal", bad, radix));
+        }
+
+        let literal = if is_float || suffix.is_some_and(|s| s.is_float()) {
+            if radix != 10 {
+                return invalid(format!("base {} literals cannot be floats", radix));
+            }
+            if suffix.is_some_and(|s| !s.is_float()) {
+                return invalid(format!("a float literal cannot have the integer suffix '{}'", suffix_text));
             }
-            _ => {
-                let current = self.source.chars().nth(self.pos).unwrap();
-                if current == '\n' {
-                    self.line += 1;
-                    self.char_pos = 1;
-                }
 
-                None
+            match digits.parse::<f64>() {
+                Ok(value) => Literal::Float { value, suffix },
+                Err(_) => return invalid(format!("'{}' is not a valid float", digits))
+            }
+        } else {
+            let max = suffix.map_or(u64::MAX as u128, |s| s.max_value());
+            match u128::from_str_radix(&digits, radix) {
+                Ok(value) if value <= max => Literal::Int { value, suffix },
+                _ => {
+                    let type_name = suffix.map_or("uint64", |s| s.type_name());
+                    return invalid(format!("literal out of range for {}", type_name));
+                }
             }
+        };
+
+        let mut token = self.make_token(TokenType::Number, start);
+        token.literal = Some(literal);
+        Ok(token)
+    }
+
+    // the first character has already been consumed
+    fn lex_operator(&mut self, start: Mark) -> Option<Token> {
+        let rest = &self.source[start.pos..];
+        let (operator, token_type) = OPERATORS.iter().find(|(operator, _)| rest.starts_with(operator))?;
+
+        for _ in 1..operator.len() {
+            self.bump();
         }
+
+        Some(self.make_token(token_type.clone(), start))
     }
 
-    pub fn peek(&mut self) -> Token {
-        // saving the current position
-        let current_pos = self.pos;
-        let current_char_pos = self.char_pos;
-        let current_line = self.line;
+    fn try_token(&mut self) -> Result<Token, LexError> {
+        let start = self.mark();
 
-        // retrieving the token
-        let token = self.next();
+        let c = if let Some(ch) = self.bump() {
+            ch
+        } else {
+            return Ok(self.make_token(TokenType::Eof, start));
+        };
 
-        // restoring the position
-        self.pos = current_pos;
-        self.char_pos = current_char_pos;
-        self.line = current_line;
+        let token = match c {
+            '"' => self.lex_string(start)?,
+            '\'' => self.lex_char(start)?,
+            'r' if self.is_raw_string_start() => self.lex_raw_string(start)?,
+            '/' if self.starts_with("//") => {
+                while let Some(next) = self.current_char() {
+                    if next == '\n' {
+                        break;
+                    }
+                    self.bump();
+                }
+
+                self.make_token(TokenType::DocComment, start)
+            }
+            '0'..='9' => self.lex_number(c, start)?,
+            c if c == '_' || c.is_xid_start() => {
+                while self.current_char().is_some_and(|c| c.is_xid_continue()) {
+                    self.bump();
+                }
+
+                // identifiers compare equal however the source happens to encode them
+                let word = &self.source[start.pos..self.pos];
+                let normalized = match is_nfc_quick(word.chars()) {
+                    IsNormalized::Yes => None,
+                    _ => Some(word.nfc().collect::<String>())
+                };
+                let word = normalized.as_deref().unwrap_or(word);
+
+                if word == "_" {
+                    self.make_token(TokenType::Underscore, start)
+                } else {
+                    let token_type = Keyword::from_word(word).map_or(TokenType::Identifier, TokenType::Keyword);
+                    let mut token = self.make_token(token_type, start);
+                    token.value = Symbol::intern(word);
+                    token
+                }
+            }
+            _ => match self.lex_operator(start) {
+                Some(token) => token,
+                None => return Err(LexError::new(LexErrorKind::UnknownCharacter(c), self.span_from(start)))
+            }
+        };
 
-        token
+        Ok(token)
     }
 
-    pub fn next(&mut self) -> Token {
+    // empty span at the position the lexer has reached
+    pub fn current_span(&self) -> Span {
+        self.span_from(self.mark())
+    }
 
-        let mut token = self.try_token();
+    fn lex_next(&mut self) -> Option<Result<Token, LexError>> {
+        if self.finished {
+            return None;
+        }
 
-        while token.is_none() {
-            token = self.try_token();
+        while let Some(trivia) = self.try_trivia(false) {
+            self.pending_trivia.push(trivia);
+        }
+        if let Some(error) = self.pending_error.take() {
+            return Some(Err(error));
         }
 
-        println!("{:?}", token);
-        token.unwrap()
+        let start = self.mark();
+        let mut token = match self.try_token() {
+            Ok(token) => token,
+            Err(error) => {
+                // keep the text so that the trivia still covers the whole source
+                self.pending_trivia.push(Trivia {
+                    kind: TriviaKind::Skipped,
+                    text: self.source[start.pos..self.pos].to_string(),
+                    span: self.span_from(start)
+                });
+                return Some(Err(error));
+            }
+        };
+
+        if token.token_type == TokenType::Eof {
+            self.finished = true;
+        } else {
+            while let Some(trivia) = self.try_trivia(true) {
+                token.trailing_trivia.push(trivia);
+            }
+        }
+        token.leading_trivia = std::mem::take(&mut self.pending_trivia);
+
+        Some(Ok(token))
     }
 
-    pub fn tokenize(str: String) -> Vec<Token> {
+    // looks `k` tokens ahead without consuming anything, `peek_nth(0)` is the next token
+    pub fn peek_nth(&mut self, k: usize) -> Option<&Result<Token, LexError>> {
+        while self.lookahead.len() <= k {
+            let token = self.lex_next()?;
+            self.lookahead.push_back(token);
+        }
+
+        self.lookahead.get(k)
+    }
+
+    pub fn peek(&mut self) -> Option<&Result<Token, LexError>> {
+        self.peek_nth(0)
+    }
+
+    // lexes the whole source, collecting every error instead of stopping at the first
+    pub fn tokenize(source: String) -> (Vec<Token>, Vec<LexError>) {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
-        let mut current_word = "".to_string();
-        let mut word_start_pos = 0;
-        let mut current_number = "".to_string();
-        let mut number_start_pos = 0;
+        for token in Lexer::new(source) {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error)
+            }
+        }
 
-        let mut char_pos = 1;
-        let mut line = 1;
+        (tokens, errors)
+    }
+}
 
-        for c in str.chars() {
-            match c {
-                '(' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::LParen, c.to_string(), char_pos, line));
-                },
-                ')' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::RParen, c.to_string(), char_pos, line));
-                },
-                ':' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::Colon, c.to_string(), char_pos, line));
-                },
-                ';' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::Semicolon, c.to_string(), char_pos, line));
-                },
-                ',' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::Comma, c.to_string(), char_pos, line));
-                },
-                '<' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::LAngle, c.to_string(), char_pos, line));
-                },
-                '>' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::RAngle, c.to_string(), char_pos, line));
-                },
-                '{' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::LBrace, c.to_string(), char_pos, line));
-                },
-                '}' => {
-                    Self::check_word_number_end(&mut tokens, &mut current_word, &mut current_number, line, &mut word_start_pos, &mut number_start_pos);
-                    tokens.push(Token::new(TokenType::RBrace, c.to_string(), char_pos, line));
-                },
-                _ => {
-                    if c.is_numeric() {
-                        if  current_word.len() != 0 {
-                            current_word.push(c);
-                        } else {
-                            current_number.push(c);
-                            if number_start_pos == 0 {
-                                number_start_pos = char_pos;
-                            }
-                        }
-
-                        continue;
-                    }
+impl Lexer {
+    // updates `tokens` and `errors` from lexing the old source to match `source`,
+    // the old source with `edit` applied; only the region the edit can affect
+    // is lexed again, the tokens after it are kept and moved into place
+    pub fn relex(source: &str, tokens: &mut Vec<Token>, errors: &mut Vec<LexError>, edit: &TextEdit) {
+        if tokens.is_empty() {
+            (*tokens, *errors) = Self::tokenize(source.to_string());
+            return;
+        }
 
-                    if c.is_alphabetic() {
-                        current_word.push(c);
-                        if word_start_pos == 0 {
-                            word_start_pos = char_pos;
-                        }
-                    } else {
-                        if Self::is_token(c) {
-                            if current_word.len() > 0 && !c.is_numeric() {
-                                tokens.push(Token::new(TokenType::Identifier, current_word, char_pos, line));
-                                current_word = "".to_string();
-                            }
-                            if current_number.len() > 0 {
-                                tokens.push(Token::new(TokenType::Number, current_number, char_pos, line));
-                                current_number = "".to_string();
-                            }
-                        } else {
-                            eprintln!("Invalid character: {}", c);
-                        }
-                    }
+        // the token the edit starts in, or the one right after it
+        let touched = tokens.iter().position(|token| token.full_span().end > edit.range.start).unwrap_or(tokens.len() - 1);
 
-                    if c == '\n' {
-                        line += 1;
-                        char_pos = 0;
-                    }
+        // back up to a token that starts its own line: the trailing trivia of the
+        // token before it ends at that newline, so no edit after it can change it
+        let mut first = touched.saturating_sub(1);
+        while first > 0 && !tokens[first].leading_trivia.first().is_some_and(|trivia| trivia.text.starts_with('\n')) {
+            first -= 1;
+        }
+
+        let restart = tokens[first].full_span();
+        let mut lexer = Lexer::resume(source.to_string(), restart);
+
+        let delta = edit.delta();
+        let edit_end = edit.range.start + edit.text.len();
+
+        let mut relexed = Vec::new();
+        let mut relexed_errors = Vec::new();
+        // old token that may end where the newest relexed token does
+        let mut old_index = first;
+
+        while let Some(token) = lexer.lex_next() {
+            let token = match token {
+                Ok(token) => token,
+                Err(error) => {
+                    relexed_errors.push(error);
+                    continue;
                 }
+            };
+
+            let end = token.full_span().end;
+            relexed.push(token);
+
+            // once past the edit the lexer only depends on its position, so an old
+            // token ending at the same (shifted) place means the rest is unchanged
+            if end < edit_end || lexer.pending_error.is_some() || lexer.finished {
+                continue;
+            }
+            let old_end = end.wrapping_add_signed(-delta);
+            if old_end < edit.range.end {
+                continue;
             }
+            while old_index < tokens.len() && tokens[old_index].full_span().end < old_end {
+                old_index += 1;
+            }
+            if old_index + 1 >= tokens.len() || tokens[old_index].full_span().end != old_end {
+                continue;
+            }
+
+            let old_position = tokens[old_index + 1].full_span();
+            let new_position = lexer.current_span();
+            let shift = Shift {
+                offset: delta,
+                lines: new_position.line as i32 - old_position.line as i32,
+                line: old_position.line,
+                columns: new_position.column as i32 - old_position.column as i32,
+                byte_columns: new_position.byte_column as i32 - old_position.byte_column as i32
+            };
 
-            char_pos += 1;
+            for token in &mut tokens[old_index + 1..] {
+                token.shift(&shift);
+            }
+            tokens.splice(first..=old_index, relexed);
+
+            let kept_before = errors.iter().take_while(|error| error.span.start < restart.start).count();
+            let kept_after = errors.iter().position(|error| error.span.start >= old_end).unwrap_or(errors.len());
+            for error in &mut errors[kept_after..] {
+                error.span = shift.apply(error.span);
+            }
+            errors.splice(kept_before..kept_after, relexed_errors);
+            return;
         }
 
+        // the edit affected everything up to the end of the file
+        tokens.truncate(first);
+        tokens.extend(relexed);
+        errors.retain(|error| error.span.start < restart.start);
+        errors.extend(relexed_errors);
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lookahead.pop_front() {
+            Some(token) => Some(token),
+            None => self.lex_next()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every token of `source` including the final Eof, failing on lex errors
+    fn tokens(source: &str) -> Vec<Token> {
+        let (tokens, errors) = Lexer::tokenize(source.to_string());
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
         tokens
     }
 
-    fn check_word_number_end(vec: &mut Vec<Token>, word: &mut String, number: &mut String, line: i32, word_pos: &mut i32, number_pos: &mut i32) {
-        if word.len() > 0 {
-            vec.push(Token::new(TokenType::Identifier, word.clone(), *word_pos, line));
-            word.clear();
-            *word_pos = 0;
+    fn types(source: &str) -> Vec<TokenType> {
+        tokens(source).into_iter().map(|token| token.token_type).collect()
+    }
+
+    fn spans(source: &str) -> Vec<(usize, usize, u32, u32)> {
+        tokens(source).iter().map(|token| (token.span.start, token.span.end, token.span.line, token.span.column)).collect()
+    }
+
+    fn literal(source: &str) -> Literal {
+        tokens(source)[0].literal.clone().unwrap()
+    }
+
+    fn error(source: &str) -> LexError {
+        let (_, errors) = Lexer::tokenize(source.to_string());
+        assert_eq!(errors.len(), 1, "expected one error: {:?}", errors);
+        errors[0].clone()
+    }
+
+    fn int(value: u128, suffix: Option<NumberSuffix>) -> Literal {
+        Literal::Int { value, suffix }
+    }
+
+    // applies `edit` through `relex` and checks the result against lexing the edited source from scratch
+    fn assert_relex(source: &str, range: Range<usize>, text: &str) {
+        let edit = TextEdit::new(range, text.to_string());
+        let edited = edit.apply(source);
+        let (mut tokens, mut errors) = Lexer::tokenize(source.to_string());
+        Lexer::relex(&edited, &mut tokens, &mut errors, &edit);
+
+        let (expected, expected_errors) = Lexer::tokenize(edited.clone());
+        assert_eq!(format!("{:#?}", tokens), format!("{:#?}", expected), "editing {:?} into {:?}", source, edited);
+        assert_eq!(errors, expected_errors, "editing {:?} into {:?}", source, edited);
+    }
+
+    #[test]
+    fn spans_are_byte_offsets_with_lines_and_columns() {
+        assert_eq!(spans("foo bar\n  baz"), vec![(0, 3, 1, 1), (4, 7, 1, 5), (10, 13, 2, 3), (13, 13, 2, 6)]);
+    }
+
+    #[test]
+    fn empty_source_is_only_eof() {
+        let tokens = tokens("");
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, TokenType::Eof);
+        assert!(tokens[0].span.is_empty());
+    }
+
+    #[test]
+    fn span_to_covers_both() {
+        let first = Span::new(4, 7, 1, 5, 5);
+        let second = Span::new(10, 13, 2, 3, 3);
+        assert_eq!(first.to(second), Span::new(4, 13, 1, 5, 5));
+        assert_eq!(first.to(second).len(), 9);
+    }
+
+    #[test]
+    fn comments_are_trivia() {
+        let tokens = tokens("// line\na /* block */ b // trailing\n");
+        assert_eq!(tokens.len(), 3);
+
+        let kinds = |trivia: &[Trivia]| trivia.iter().map(|trivia| trivia.kind).collect::<Vec<_>>();
+        assert_eq!(kinds(&tokens[0].leading_trivia), vec![TriviaKind::LineComment, TriviaKind::Whitespace]);
+        assert_eq!(kinds(&tokens[0].trailing_trivia), vec![TriviaKind::Whitespace, TriviaKind::BlockComment, TriviaKind::Whitespace]);
+        assert_eq!(kinds(&tokens[1].trailing_trivia), vec![TriviaKind::Whitespace, TriviaKind::LineComment]);
+        assert_eq!(tokens[1].trailing_trivia[1].text, "// trailing");
+        // the newline after a token belongs to the next one
+        assert_eq!(kinds(&tokens[2].leading_trivia), vec![TriviaKind::Whitespace]);
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        let tokens = tokens("/* a /* b */ c */ x");
+        assert_eq!(tokens[0].token_type, TokenType::Identifier);
+        assert_eq!(tokens[0].leading_trivia[0].text, "/* a /* b */ c */");
+    }
+
+    #[test]
+    fn doc_comments_are_tokens() {
+        assert_eq!(types("/// doc\n//// not doc\nx"), vec![TokenType::DocComment, TokenType::Identifier, TokenType::Eof]);
+    }
+
+    #[test]
+    fn unterminated_block_comment_runs_to_the_end() {
+        let (tokens, errors) = Lexer::tokenize("a /* b /* c */".to_string());
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, LexErrorKind::UnterminatedBlockComment);
+        assert_eq!((errors[0].span.start, errors[0].span.end), (2, 14));
+    }
+
+    #[test]
+    fn strings_decode_escapes() {
+        assert_eq!(literal(r#""a\n\t\r\0\\\"\'""#), Literal::Str("a\n\t\r\0\\\"'".to_string()));
+        assert_eq!(literal(r#""\u{48}\u{1F600}""#), Literal::Str("H\u{1F600}".to_string()));
+        assert_eq!(literal("\"two\nlines\""), Literal::Str("two\nlines".to_string()));
+    }
+
+    #[test]
+    fn raw_strings_keep_backslashes() {
+        assert_eq!(literal(r#"r"a\nb""#), Literal::Str("a\\nb".to_string()));
+        assert_eq!(literal(r###"r#"say "hi""#"###), Literal::Str("say \"hi\"".to_string()));
+    }
+
+    #[test]
+    fn chars() {
+        assert_eq!(literal("'a'"), Literal::Char('a'));
+        assert_eq!(literal(r"'\''"), Literal::Char('\''));
+        assert_eq!(literal("'é'"), Literal::Char('é'));
+        assert_eq!(error("''").kind, LexErrorKind::EmptyChar);
+        assert_eq!(error("'ab'").kind, LexErrorKind::CharTooLong);
+    }
+
+    #[test]
+    fn unterminated_literals_point_at_their_start() {
+        let string = error("x = \"abc\ndef");
+        assert_eq!(string.kind, LexErrorKind::UnterminatedString);
+        assert_eq!((string.span.start, string.span.line, string.span.column), (4, 1, 5));
+
+        let char = error("x = 'a\n");
+        assert_eq!(char.kind, LexErrorKind::UnterminatedChar);
+        assert_eq!(char.span.start, 4);
+    }
+
+    #[test]
+    fn bad_escapes_are_reported() {
+        assert_eq!(error(r#""\q""#).kind, LexErrorKind::UnknownEscape('q'));
+        assert!(matches!(error(r#""\u{110000}""#).kind, LexErrorKind::InvalidUnicodeEscape(_)));
+        assert!(matches!(error(r#""\u48""#).kind, LexErrorKind::InvalidUnicodeEscape(_)));
+    }
+
+    #[test]
+    fn integer_bases_and_separators() {
+        assert_eq!(literal("1_000"), int(1000, None));
+        assert_eq!(literal("0xff_FF"), int(0xffff, None));
+        assert_eq!(literal("0b1010"), int(10, None));
+        assert_eq!(literal("0o17"), int(15, None));
+        assert_eq!(literal("10u8"), int(10, Some(NumberSuffix::U8)));
+        assert_eq!(literal("0x7fi64"), int(0x7f, Some(NumberSuffix::I64)));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(literal("1.5"), Literal::Float { value: 1.5, suffix: None });
+        assert_eq!(literal("2e3"), Literal::Float { value: 2000.0, suffix: None });
+        assert_eq!(literal("1.5E-1"), Literal::Float { value: 0.15, suffix: None });
+        assert_eq!(literal("3f32"), Literal::Float { value: 3.0, suffix: Some(NumberSuffix::F32) });
+    }
+
+    #[test]
+    fn ranges_and_fields_are_not_floats() {
+        assert_eq!(types("1..2"), vec![TokenType::Number, TokenType::DotDot, TokenType::Number, TokenType::Eof]);
+        assert_eq!(types("1.x"), vec![TokenType::Number, TokenType::Dot, TokenType::Identifier, TokenType::Eof]);
+    }
+
+    #[test]
+    fn number_at_the_end_of_the_file() {
+        assert_eq!(literal("42"), int(42, None));
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for source in ["0b102", "10q", "0x", "300u8", "129i8", "1.5u8", "18446744073709551616"] {
+            assert!(matches!(error(source).kind, LexErrorKind::InvalidNumber(_)), "{} should be invalid", source);
         }
-        if number.len() > 0 {
-            vec.push(Token::new(TokenType::Number, number.clone(), *number_pos, line));
-            number.clear();
-            *number_pos = 0;
+    }
+
+    #[test]
+    fn signed_literals_allow_the_minimum_magnitude() {
+        assert_eq!(literal("128i8"), int(128, Some(NumberSuffix::I8)));
+        assert_eq!(NumberSuffix::I8.max_positive(), 127);
+        assert_eq!(NumberSuffix::U8.max_positive(), 255);
+    }
+
+    #[test]
+    fn operators_are_maximal_munch() {
+        use TokenType::*;
+        assert_eq!(types("<<= >>= ..= << >> <= >= == != && || -> .. ."), vec![
+            ShiftLeftEqual, ShiftRightEqual, DotDotEqual, ShiftLeft, ShiftRight, LessEqual, GreaterEqual,
+            EqualEqual, BangEqual, AndAnd, OrOr, Arrow, DotDot, Dot, Eof
+        ]);
+        assert_eq!(types("a<<=b"), vec![Identifier, ShiftLeftEqual, Identifier, Eof]);
+        assert_eq!(types("---"), vec![Minus, Minus, Minus, Eof]);
+        assert_eq!(types("=>"), vec![Equal, RAngle, Eof]);
+    }
+
+    #[test]
+    fn every_operator_is_lexed() {
+        for (operator, token_type) in OPERATORS {
+            let tokens = tokens(operator);
+            assert_eq!(tokens.len(), 2, "'{}' should be one token", operator);
+            assert_eq!(&tokens[0].token_type, token_type);
         }
     }
 
-    fn is_token(c: char) -> bool {
-        match c {
-            '(' => true,
-            ')' => true,
-            ':' => true,
-            ';' => true,
-            ',' => true,
-            '<' => true,
-            '>' => true,
-            '{' => true,
-            '}' => true,
-            _ => {
-                if c.is_numeric() {
-                    true
-                } else {
-                    if c.is_alphabetic() || c.is_whitespace() {
-                        true
-                    } else {
-                        false
-                    }
-                }
+    #[test]
+    fn keywords_are_their_own_tokens() {
+        for word in ["proct", "pub", "priv", "refcounted", "func", "return", "struct", "val", "var", "if", "else",
+                     "switch", "while", "for", "in", "break", "continue", "self"] {
+            let keyword = Keyword::from_word(word).unwrap();
+            assert_eq!(keyword.as_str(), word);
+            assert_eq!(types(word), vec![TokenType::Keyword(keyword), TokenType::Eof]);
+        }
+        assert_eq!(types("funcs _ _x"), vec![TokenType::Identifier, TokenType::Underscore, TokenType::Identifier, TokenType::Eof]);
+    }
+
+    #[test]
+    fn peek_nth_does_not_consume() {
+        let mut lexer = Lexer::new("a b c".to_string());
+        let value = |token: Option<&Result<Token, LexError>>| token.unwrap().as_ref().unwrap().value.to_string();
+        assert_eq!(value(lexer.peek_nth(2)), "c");
+        assert_eq!(value(lexer.peek()), "a");
+
+        let values = lexer.map(|token| token.unwrap().token_type).collect::<Vec<_>>();
+        assert_eq!(values, vec![TokenType::Identifier, TokenType::Identifier, TokenType::Identifier, TokenType::Eof]);
+    }
+
+    #[test]
+    fn iteration_stops_after_eof() {
+        let mut lexer = Lexer::new("x".to_string());
+        assert!(lexer.next().is_some());
+        assert_eq!(lexer.next().unwrap().unwrap().token_type, TokenType::Eof);
+        assert!(lexer.next().is_none());
+        assert!(lexer.peek_nth(3).is_none());
+    }
+
+    #[test]
+    fn lexing_continues_after_errors() {
+        let (tokens, errors) = Lexer::tokenize("a € b ` c 12q d".to_string());
+        let kinds = errors.iter().map(|error| error.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            LexErrorKind::UnknownCharacter('€'),
+            LexErrorKind::UnknownCharacter('`'),
+            LexErrorKind::InvalidNumber("invalid suffix 'q'".to_string())
+        ]);
+        let names = tokens.iter().filter(|token| token.token_type == TokenType::Identifier).map(|token| token.value.to_string()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b", "c", "d"]);
+
+        // the unknown character is 3 bytes but one column
+        assert_eq!((errors[0].span.start, errors[0].span.end, errors[0].span.column), (2, 5, 3));
+    }
+
+    #[test]
+    fn identifier_or_number_at_the_end_of_the_file() {
+        assert_eq!(types("abc"), vec![TokenType::Identifier, TokenType::Eof]);
+        assert_eq!(types("1"), vec![TokenType::Number, TokenType::Eof]);
+    }
+
+    #[test]
+    fn skipped_text_stays_in_the_trivia() {
+        let (tokens, _) = Lexer::tokenize("a ` b".to_string());
+        assert!(tokens[1].leading_trivia.iter().any(|trivia| trivia.kind == TriviaKind::Skipped && trivia.text == "`"));
+    }
+
+    #[test]
+    fn unicode_identifiers_follow_xid() {
+        let names = tokens("größe π _x x\u{301}1").iter().map(|token| token.value.to_string()).collect::<Vec<_>>();
+        assert_eq!(&names[..4], ["größe", "π", "_x", "x\u{301}1"]);
+
+        // combining marks may only continue an identifier
+        let (_, errors) = Lexer::tokenize("\u{301}a".to_string());
+        assert_eq!(errors[0].kind, LexErrorKind::UnknownCharacter('\u{301}'));
+    }
+
+    #[test]
+    fn identifiers_are_nfc_normalized() {
+        let tokens = tokens("e\u{301} \u{e9}");
+        assert_eq!(tokens[0].value, tokens[1].value);
+        assert_eq!(tokens[0].value.as_str(), "\u{e9}");
+        // the span still covers the text as written
+        assert_eq!(tokens[0].span.len(), 3);
+    }
+
+    #[test]
+    fn columns_count_scalars_and_bytes() {
+        let tokens = tokens("ö = 'ü' x\n\tπ");
+        let columns = tokens.iter().map(|token| (token.span.line, token.span.column, token.span.byte_column)).collect::<Vec<_>>();
+        assert_eq!(columns, vec![(1, 1, 1), (1, 3, 4), (1, 5, 6), (1, 9, 11), (2, 2, 2), (2, 3, 4)]);
+    }
+
+    #[test]
+    fn nul_is_an_unknown_character() {
+        let (tokens, errors) = Lexer::tokenize("a\0b c".to_string());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, LexErrorKind::UnknownCharacter('\0'));
+        assert_eq!(tokens.iter().map(|token| token.token_type.clone()).collect::<Vec<_>>(),
+            vec![TokenType::Identifier, TokenType::Identifier, TokenType::Identifier, TokenType::Eof]);
+    }
+
+    #[test]
+    fn relex_opening_and_closing_block_comments() {
+        let source = "a b\nc /* d */ e\nf \"g\" h\n";
+        assert_relex(source, 4..4, "/*");
+        assert_relex(source, 2..2, "/* ");
+        assert_relex(source, 6..8, "");
+        assert_relex(source, 11..13, "");
+        assert_relex("a /* b\nc d\ne f", 9..9, "*/");
+        assert_relex("a /* b\nc d\ne f", 14..14, " */");
+        assert_relex("a /* /* b */\nc */ d", 5..7, "");
+    }
+
+    #[test]
+    fn relex_opening_and_closing_strings() {
+        let source = "a b\nc \"d\" e\nf g\n";
+        assert_relex(source, 2..2, "\"");
+        assert_relex(source, 6..7, "");
+        assert_relex(source, 8..9, "");
+        assert_relex("x \"ab\ny z", 9..9, "\"");
+        assert_relex("x = \"a\\\" b\nc", 6..7, "");
+    }
+
+    #[test]
+    fn relex_multi_line_edits() {
+        let source = "f: func {\n    val x = 1;\n    val y = 2;\n}\n";
+        assert_relex(source, 14..32, "var z =\n  3;\n  foo(");
+        assert_relex(source, 9..42, "");
+        assert_relex(source, 0..0, "// new\n/// doc\n");
+        assert_relex(source, source.len()..source.len(), "\ng: func { }");
+        assert_relex("", 0..0, "a\nb");
+    }
+
+    #[test]
+    fn relex_every_position() {
+        let source = "a: func {\n  /* c */ x = \"s\\n\" + 'c';\n  y /* d\n */ z; // e\n}\n";
+        let boundaries = (0..=source.len()).filter(|i| source.is_char_boundary(*i)).collect::<Vec<_>>();
+        for &start in &boundaries {
+            for text in ["/*", "*/", "\"", "'", "\n", "x", "//"] {
+                assert_relex(source, start..start, text);
+            }
+            for &end in boundaries.iter().filter(|end| **end > start).take(3) {
+                assert_relex(source, start..end, "");
+                assert_relex(source, start..end, "\"\n");
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn only_names_are_interned() {
+        let tokens = tokens("x func \"text\" 12 + /// doc");
+        let values = tokens.iter().map(|token| token.value).collect::<Vec<_>>();
+        assert_eq!(values, vec![Symbol::intern("x"), Symbol::intern("func"), Symbol::EMPTY, Symbol::EMPTY, Symbol::EMPTY, Symbol::EMPTY, Symbol::EMPTY]);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod checker;
+pub mod codegen;
+pub mod cst;
+pub mod diagnostic;
+pub mod emitter;
+pub mod error_code;
+pub mod lexer;
+pub mod parser;
+pub mod pair;
+pub mod symbol;
@@ -1,32 +1,92 @@
-extern crate core;
-
-mod lexer;
-mod parser;
-mod pair;
-
 use std::*;
-use lexer::{Token, Lexer, TokenType};
-use parser::{Parser};
+use std::io::IsTerminal;
+use dust::checker::{Checker, Receivers};
+use dust::emitter::{Emitter, ErrorFormat};
+use dust::error_code::ErrorCode;
+use dust::lexer::Lexer;
+use dust::parser::Parser;
 
 fn print_command_usage(program: String) {
-    eprintln!("Usage: {} <file>", program);
+    eprintln!("Usage: {} [--error-format=human|json] [--dump-ast] <file>", program);
+    eprintln!("       {} explain <code>", program);
+}
+
+fn explain(code: &str) {
+    match ErrorCode::from_name(code) {
+        Some(code) => println!("{}", code.explanation()),
+        None => {
+            eprintln!("'{}' is not a known error code", code);
+            process::exit(1);
+        }
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
 
-    if args.len() != 2 {
+    if args.len() == 3 && args[1] == "explain" {
+        explain(&args[2]);
+        return;
+    }
+
+    let mut error_format = ErrorFormat::Human;
+    let mut dump_ast = false;
+    let mut files = Vec::new();
+    for arg in &args[1..] {
+        if arg == "--dump-ast" {
+            dump_ast = true;
+        } else if let Some(name) = arg.strip_prefix("--error-format=") {
+            match ErrorFormat::from_name(name) {
+                Some(format) => error_format = format,
+                None => {
+                    eprintln!("Unknown error format '{}'", name);
+                    print_command_usage(program);
+                    process::exit(1);
+                }
+            }
+        } else {
+            files.push(arg.clone());
+        }
+    }
+
+    if files.len() != 1 {
         print_command_usage(program);
         return;
     }
+    let filename = &files[0];
 
-    let file = fs::read_to_string(&args[1]).unwrap();
+    let file = fs::read_to_string(filename).unwrap();
     let lexer = Lexer::new(file.clone());
 
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse(args[1].to_string());
-    println!("AST: {:?}", ast);
-    let splitted = args[1].split(".").collect::<Vec<&str>>()[0].to_string();
-    Parser::print_debug_pseudo_asm(ast, fs::File::create(format!("{}.asm", splitted)).unwrap());
+    let ast = parser.parse(filename.to_string());
+    if dump_ast {
+        println!("AST: {:?}", ast);
+    }
+
+    // checking a file with syntax errors would only report follow-up errors
+    let mut diagnostics = parser.take_diagnostics();
+    let mut receivers = Receivers::new();
+    if !diagnostics.has_errors() {
+        let (checked, resolved) = Checker::resolve(&ast);
+        diagnostics.extend(checked);
+        receivers = resolved;
+    }
+
+    // colour follows stderr rather than stdout since that's where diagnostics go,
+    // `dust file.dust > out` still gets colour and `2> log` doesn't
+    let emitter = Emitter::new(filename, &file, error_format, io::stderr().is_terminal());
+    let mut stderr = io::stderr().lock();
+    for diagnostic in diagnostics.iter() {
+        emitter.emit(diagnostic, &mut stderr).unwrap();
+    }
+    if diagnostics.has_errors() {
+        if error_format == ErrorFormat::Human {
+            eprintln!("For more information about an error, try `{} explain <code>`.", program);
+        }
+        process::exit(1);
+    }
+    let splitted = filename.split(".").collect::<Vec<&str>>()[0].to_string();
+    Parser::print_debug_pseudo_asm(ast, receivers, fs::File::create(format!("{}.asm", splitted)).unwrap()).unwrap();
 }
@@ -1,329 +1,1688 @@
-
-use crate::{Lexer, Token, TokenType};
+use crate::checker::Receivers;
+use crate::codegen::CodeGen;
+use crate::cst::{NodeKind, SyntaxElement, SyntaxNode, SyntaxTree};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::error_code::ErrorCode;
+use crate::lexer::{Keyword, Lexer, Literal, Span, Token, TokenType, Trivia, TriviaKind};
 use crate::pair::Pair;
-use std::fs;
+use crate::symbol::Symbol;
+use std::fmt;
 use std::fs::File;
-use std::io::Write;
+use std::io;
+
+// a type as written in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    // int32, string, point
+    Named(Symbol),
+    // name<args>, e.g. Map<string, int32>
+    Generic { name: Symbol, args: Vec<Type> },
+    // func(params): return_type, parameter names are not part of the type
+    Function { params: Vec<Type>, return_type: Box<Type> },
+    // Array<element>
+    Array(Box<Type>),
+    // *pointee
+    Pointer(Box<Type>),
+    // &referent
+    Reference(Box<Type>),
+}
+
+impl Type {
+    pub fn named(name: &str) -> Type {
+        Type::Named(Symbol::intern(name))
+    }
+
+    pub fn void() -> Type {
+        Type::Named(Symbol::VOID)
+    }
+
+    pub fn never() -> Type {
+        Type::Named(Symbol::NEVER)
+    }
+
+    // the name of a Named type
+    pub fn name(&self) -> Option<Symbol> {
+        match self {
+            Type::Named(name) => Some(*name),
+            _ => None
+        }
+    }
+
+    pub fn is_void(&self) -> bool {
+        *self == Type::void()
+    }
+
+    pub fn is_never(&self) -> bool {
+        *self == Type::never()
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Generic { name, args } => {
+                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+                write!(f, "{}<{}>", name, args.join(", "))
+            }
+            Type::Function { params, return_type } => {
+                let params = params.iter().map(|param| param.to_string()).collect::<Vec<_>>();
+                write!(f, "func({}): {}", params.join(", "), return_type)
+            }
+            Type::Array(element) => write!(f, "Array<{}>", element),
+            Type::Pointer(pointee) => write!(f, "*{}", pointee),
+            Type::Reference(referent) => write!(f, "&{}", referent)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOperator {
+    // -x
+    Negate,
+    // !x
+    Not,
+    // ~x
+    BitNot,
+}
+
+impl UnaryOperator {
+    fn from_token(token_type: &TokenType) -> Option<UnaryOperator> {
+        match token_type {
+            TokenType::Minus => Some(UnaryOperator::Negate),
+            TokenType::Bang => Some(UnaryOperator::Not),
+            TokenType::Tilde => Some(UnaryOperator::BitNot),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+            UnaryOperator::BitNot => "~"
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl BinaryOperator {
+    fn from_token(token_type: &TokenType) -> Option<BinaryOperator> {
+        match token_type {
+            TokenType::Plus => Some(BinaryOperator::Add),
+            TokenType::Minus => Some(BinaryOperator::Subtract),
+            TokenType::Star => Some(BinaryOperator::Multiply),
+            TokenType::Slash => Some(BinaryOperator::Divide),
+            TokenType::Percent => Some(BinaryOperator::Remainder),
+            TokenType::EqualEqual => Some(BinaryOperator::Equal),
+            TokenType::BangEqual => Some(BinaryOperator::NotEqual),
+            TokenType::LAngle => Some(BinaryOperator::Less),
+            TokenType::LessEqual => Some(BinaryOperator::LessEqual),
+            TokenType::RAngle => Some(BinaryOperator::Greater),
+            TokenType::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+            TokenType::AndAnd => Some(BinaryOperator::And),
+            TokenType::OrOr => Some(BinaryOperator::Or),
+            TokenType::Ampersand => Some(BinaryOperator::BitAnd),
+            TokenType::Pipe => Some(BinaryOperator::BitOr),
+            TokenType::Caret => Some(BinaryOperator::BitXor),
+            TokenType::ShiftLeft => Some(BinaryOperator::ShiftLeft),
+            TokenType::ShiftRight => Some(BinaryOperator::ShiftRight),
+            _ => None
+        }
+    }
+
+    // higher binds tighter, every level is left associative except
+    // comparisons, which don't chain
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual |
+            BinaryOperator::Less | BinaryOperator::LessEqual |
+            BinaryOperator::Greater | BinaryOperator::GreaterEqual => 3,
+            BinaryOperator::BitOr => 4,
+            BinaryOperator::BitXor => 5,
+            BinaryOperator::BitAnd => 6,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 7,
+            BinaryOperator::Add | BinaryOperator::Subtract => 8,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 9
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == 3
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>"
+        }
+    }
+}
+
+// the operator of a compound assignment such as `+=`
+fn compound_assignment(token_type: &TokenType) -> Option<BinaryOperator> {
+    match token_type {
+        TokenType::PlusEqual => Some(BinaryOperator::Add),
+        TokenType::MinusEqual => Some(BinaryOperator::Subtract),
+        TokenType::StarEqual => Some(BinaryOperator::Multiply),
+        TokenType::SlashEqual => Some(BinaryOperator::Divide),
+        TokenType::PercentEqual => Some(BinaryOperator::Remainder),
+        TokenType::AmpersandEqual => Some(BinaryOperator::BitAnd),
+        TokenType::PipeEqual => Some(BinaryOperator::BitOr),
+        TokenType::CaretEqual => Some(BinaryOperator::BitXor),
+        TokenType::ShiftLeftEqual => Some(BinaryOperator::ShiftLeft),
+        TokenType::ShiftRightEqual => Some(BinaryOperator::ShiftRight),
+        _ => None
+    }
+}
+
+// unary operators bind tighter than every binary one
+const UNARY_PRECEDENCE: u8 = 10;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Visibility {
+    Public,
+    Private,
+    // the default when nothing is written
+    Protected,
+}
+
+impl Visibility {
+    fn from_keyword(keyword: Keyword) -> Option<Visibility> {
+        match keyword {
+            Keyword::Pub => Some(Visibility::Public),
+            Keyword::Priv => Some(Visibility::Private),
+            Keyword::Proct => Some(Visibility::Protected),
+            _ => None
+        }
+    }
 
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Private => "private",
+            Visibility::Protected => "protected"
+        }
+    }
+}
+
+// [visibility] name: Type;
 #[derive(Debug, Clone)]
-pub struct Type {
-    pub name: String,
-    pub subtype: Option<Box<Type>>,
+pub struct Field {
+    pub name: Symbol,
+    pub visibility: Visibility,
+    pub field_type: Type,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+// a method or constructor, `function` is a FunctionDefinition whose args
+// don't include the implicit `self`
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub visibility: Visibility,
+    pub function: AST,
+    pub span: Span,
+}
+
+// pattern -> value, a missing pattern is the wildcard `_`
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: Option<AST>,
+    pub value: AST,
+    pub span: Span,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone)]
 pub enum AST {
     File { child: Vec<AST>, filename: String },
-    Return { value: Box<AST> },
-    Value { value: String },
-    FunctionCall { name: String, args: Vec<AST> },
-    FunctionDefinition { name: String, args: Vec<Pair<String, Type>>, body: Vec<AST>, return_type: String },
-    None,
+    // `value` is AST::None for a bare `return;`
+    Return { value: Box<AST>, span: Span },
+    Identifier { name: Symbol, span: Span },
+    Literal { value: Literal, span: Span },
+    Unary { operator: UnaryOperator, operand: Box<AST>, span: Span },
+    Binary { operator: BinaryOperator, left: Box<AST>, right: Box<AST>, span: Span },
+    Call { callee: Box<AST>, args: Vec<AST>, span: Span },
+    // object.field
+    Field { object: Box<AST>, field: Symbol, span: Span },
+    // object[index]
+    Index { object: Box<AST>, index: Box<AST>, span: Span },
+    // target = value, or target op= value when `operator` is set
+    Assign { target: Box<AST>, operator: Option<BinaryOperator>, value: Box<AST>, span: Span },
+    // val name: variable_type = value, `var` when mutable
+    VariableDeclaration { name: Symbol, mutable: bool, variable_type: Option<Type>, value: Box<AST>, span: Span },
+    // `value` is the trailing expression without a ';', if any
+    Block { body: Vec<AST>, value: Option<Box<AST>>, span: Span },
+    // if condition { } else { }, `else_branch` is a Block or another If
+    If { condition: Box<AST>, then_branch: Box<AST>, else_branch: Option<Box<AST>>, span: Span },
+    // if scrutinee == { pattern -> value; ... }, the first arm where
+    // `scrutinee operator pattern` holds is taken
+    IfCompare { scrutinee: Box<AST>, operator: BinaryOperator, arms: Vec<Arm>, span: Span },
+    // switch scrutinee { pattern -> value; ... }, the first arm whose pattern
+    // equals the scrutinee is taken
+    Switch { scrutinee: Box<AST>, arms: Vec<Arm>, span: Span },
+    // start..end, or start..=end when inclusive, only in the head of a for loop
+    Range { start: Box<AST>, end: Box<AST>, inclusive: bool, span: Span },
+    // [label:] while condition { }
+    While { label: Option<Symbol>, condition: Box<AST>, body: Box<AST>, span: Span },
+    // [label:] for variable in iterable { }, `iterable` is a Range or an Array
+    For { label: Option<Symbol>, variable: Symbol, iterable: Box<AST>, body: Box<AST>, span: Span },
+    // break [label], continue [label]
+    Break { label: Option<Symbol>, span: Span },
+    Continue { label: Option<Symbol>, span: Span },
+    FunctionDefinition { name: Symbol, args: Vec<Pair<Symbol, Type>>, body: Vec<AST>, return_type: Type },
+    // name: struct { fields, construct(args) { }, name: func(args) { } }
+    StructDefinition { name: Symbol, fields: Vec<Field>, constructors: Vec<Method>, methods: Vec<Method>, span: Span },
+    // name { field: value, ... }
+    StructLiteral { name: Symbol, fields: Vec<Pair<Symbol, AST>>, span: Span },
+    // `_`, or the missing value of a bare `return;` positioned at the `return`
+    None { span: Span },
 }
 
+impl AST {
+    // expressions ending in a block need no ';' when used as a statement
+    pub fn ends_with_block(&self) -> bool {
+        matches!(self, AST::Block { .. } | AST::If { .. } | AST::IfCompare { .. } | AST::Switch { .. } | AST::While { .. } | AST::For { .. })
+    }
+
+    // the value of an integer or char literal pattern, possibly negated
+    pub fn integer_constant(&self) -> Option<i128> {
+        match self {
+            AST::Literal { value: Literal::Int { value, .. }, .. } => i128::try_from(*value).ok(),
+            AST::Literal { value: Literal::Char(c), .. } => Some(*c as i128),
+            AST::Unary { operator: UnaryOperator::Negate, operand, .. } => operand.integer_constant().map(|value| -value),
+            _ => None
+        }
+    }
+
+    // the nodes directly below this one, in source order
+    pub fn children(&self) -> Vec<&AST> {
+        match self {
+            AST::File { child, .. } => child.iter().collect(),
+            AST::Return { value, .. } => vec![value],
+            AST::Unary { operand, .. } => vec![operand],
+            AST::Binary { left, right, .. } => vec![left, right],
+            AST::Call { callee, args, .. } => std::iter::once(callee.as_ref()).chain(args).collect(),
+            AST::Field { object, .. } => vec![object],
+            AST::Index { object, index, .. } => vec![object, index],
+            AST::Assign { target, value, .. } => vec![target, value],
+            AST::VariableDeclaration { value, .. } => vec![value],
+            AST::Block { body, value, .. } => body.iter().chain(value.as_deref()).collect(),
+            AST::If { condition, then_branch, else_branch, .. } => {
+                vec![condition.as_ref(), then_branch.as_ref()].into_iter().chain(else_branch.as_deref()).collect()
+            }
+            AST::IfCompare { scrutinee, arms, .. } | AST::Switch { scrutinee, arms, .. } => {
+                let arms = arms.iter().flat_map(|arm| arm.pattern.iter().chain(std::iter::once(&arm.value)));
+                std::iter::once(scrutinee.as_ref()).chain(arms).collect()
+            }
+            AST::Range { start, end, .. } => vec![start, end],
+            AST::While { condition, body, .. } => vec![condition, body],
+            AST::For { iterable, body, .. } => vec![iterable, body],
+            AST::FunctionDefinition { body, .. } => body.iter().collect(),
+            AST::StructDefinition { constructors, methods, .. } => constructors.iter().chain(methods).map(|method| &method.function).collect(),
+            AST::StructLiteral { fields, .. } => fields.iter().map(|field| &field.1).collect(),
+            AST::Identifier { .. } | AST::Literal { .. } | AST::Break { .. } | AST::Continue { .. } | AST::None { .. } => Vec::new()
+        }
+    }
+
+    // where the node was written, files and functions return an empty span
+    pub fn span(&self) -> Span {
+        match self {
+            AST::Return { span, .. } |
+            AST::Identifier { span, .. } |
+            AST::Literal { span, .. } |
+            AST::Unary { span, .. } |
+            AST::Binary { span, .. } |
+            AST::Call { span, .. } |
+            AST::Field { span, .. } |
+            AST::Index { span, .. } |
+            AST::Assign { span, .. } |
+            AST::VariableDeclaration { span, .. } |
+            AST::Block { span, .. } |
+            AST::If { span, .. } |
+            AST::IfCompare { span, .. } |
+            AST::Switch { span, .. } |
+            AST::Range { span, .. } |
+            AST::While { span, .. } |
+            AST::For { span, .. } |
+            AST::Break { span, .. } |
+            AST::Continue { span, .. } |
+            AST::StructDefinition { span, .. } |
+            AST::StructLiteral { span, .. } |
+            AST::None { span } => *span,
+            _ => Span::default()
+        }
+    }
+}
+
+// parse functions report a problem by returning it, the caller recovers
+type ParseResult<T> = Result<T, Box<Diagnostic>>;
 
 #[derive(Debug)]
 pub struct Parser {
-    lexer: Lexer,
-    current_token: Token,
+    tree: SyntaxTree,
+    // index of the next token in `tree`
+    position: usize,
+    // how many bracket groups each token in `tree` is nested in, the tree is
+    // only used for this and for its tokens, recovery uses the depths to find
+    // where a declaration ends
+    depths: Vec<u32>,
+    // of the function being parsed
+    return_type: Type,
+    // set while parsing a condition, where `name {` starts the body and not a struct literal
+    no_struct_literal: bool,
+    // what is left of a token whose leading '>' closed type arguments, it comes
+    // before the token at `position`
+    split_token: Option<Token>,
+    diagnostics: Diagnostics,
 }
 
 impl Parser {
     pub fn new(lexer: Lexer) -> Parser {
+        Self::from_syntax_tree(SyntaxTree::build(lexer))
+    }
+
+    pub fn from_syntax_tree(tree: SyntaxTree) -> Parser {
+        let mut depths = vec![0; tree.tokens().len()];
+        Self::record_depths(tree.root(), 0, &mut depths);
+
+        let mut diagnostics = Diagnostics::new();
+        for error in tree.errors() {
+            diagnostics.push(Diagnostic::from(error));
+        }
+
         Self {
-            lexer,
-            current_token: Token::new(TokenType::EOF, "".to_string(), -1, -1),
+            tree,
+            position: 0,
+            depths,
+            return_type: Type::void(),
+            no_struct_literal: false,
+            split_token: None,
+            diagnostics,
         }
     }
 
+    fn record_depths(node: &SyntaxNode, depth: u32, depths: &mut [u32]) {
+        for (index, child) in node.children.iter().enumerate() {
+            match child {
+                SyntaxElement::Node(group) => Self::record_depths(group, depth + 1, depths),
+                // the delimiters belong to the enclosing level
+                SyntaxElement::Token(token) => {
+                    let delimiter = node.kind != NodeKind::File && (index == 0 || index == node.children.len() - 1);
+                    depths[*token] = if delimiter { depth - 1 } else { depth };
+                }
+            }
+        }
+    }
+
+    pub fn syntax_tree(&self) -> &SyntaxTree {
+        &self.tree
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        std::mem::take(&mut self.diagnostics)
+    }
+
     pub fn parse(&mut self, filename: String) -> AST {
-        let mut file = AST::File {
-            child: Vec::new(),
+        let mut child = Vec::new();
+
+        while !self.at(TokenType::Eof) {
+            match self.parse_item() {
+                Ok(item) => child.push(item),
+                Err(diagnostic) => {
+                    self.report(*diagnostic);
+                    self.synchronize_item();
+                }
+            }
+        }
+
+        self.diagnostics.sort();
+        AST::File {
+            child,
             filename,
+        }
+    }
+
+    // name: func[(args)] [: return_type | -> return_type] { body }
+    // name: struct { members }
+    fn parse_item(&mut self) -> ParseResult<AST> {
+        let name = self.expect_name("a declaration")?;
+        self.expect(TokenType::Colon, &format!("':' after identifier '{}'", name.value))?;
+        if self.eat(TokenType::Keyword(Keyword::Struct)) {
+            return self.parse_struct(name);
+        }
+        self.parse_function(name)
+    }
+
+    // func[(args)] [: return_type | -> return_type] { body }, after the name and ':'
+    fn parse_function(&mut self, name: Token) -> ParseResult<AST> {
+        self.expect(TokenType::Keyword(Keyword::Func), "'func' or 'struct' after ':'")?;
+        let (args, return_type) = self.parse_signature()?;
+        self.parse_function_body(name, args, return_type)
+    }
+
+    // [(args)] [: return_type | -> return_type] after 'func'
+    fn parse_signature(&mut self) -> ParseResult<(Vec<Pair<Symbol, Type>>, Type)> {
+        // a function without arguments may leave out the parentheses
+        let mut args = Vec::new();
+        if self.eat(TokenType::LParen) {
+            args = self.parse_arguments()?;
+        }
+
+        Ok((args, self.parse_return_type()?))
+    }
+
+    // `: Type` or `-> Type`, void when left out
+    fn parse_return_type(&mut self) -> ParseResult<Type> {
+        if self.eat(TokenType::Colon) || self.eat(TokenType::Arrow) {
+            return self.parse_type();
+        }
+
+        Ok(Type::void())
+    }
+
+    fn parse_function_body(&mut self, name: Token, args: Vec<Pair<Symbol, Type>>, return_type: Type) -> ParseResult<AST> {
+        self.expect(TokenType::LBrace, &format!("'{{' after return type '{}'", return_type))?;
+        self.return_type = return_type.clone();
+        let (mut body, value) = self.parse_body();
+        // a function's value is only ever given by `return`
+        body.extend(value);
+
+        let next = self.peek_token();
+        if next.token_type != TokenType::RBrace {
+            return Err(Diagnostic::error(ErrorCode::UnclosedFunction, "Not closing function.", next.span)
+                .with_label(name.span, format!("function '{}' starts here", name.value))
+                .with_help("add a '}' to close the function body").into());
+        }
+        self.next_token();
+
+        Ok(AST::FunctionDefinition {
+            name: name.value,
+            args,
+            body,
+            return_type,
+        })
+    }
+
+    // { [visibility] field: Type; [visibility] construct(args) { } [visibility] method: func(args) { } }
+    fn parse_struct(&mut self, name: Token) -> ParseResult<AST> {
+        self.expect(TokenType::LBrace, "'{' after 'struct'")?;
+
+        let mut fields = Vec::new();
+        let mut constructors = Vec::new();
+        let mut methods = Vec::new();
+        while !self.at(TokenType::RBrace) && !self.at(TokenType::Eof) {
+            let start = self.peek_token().span;
+            let visibility = match self.peek_token().token_type {
+                TokenType::Keyword(keyword) => Visibility::from_keyword(keyword),
+                _ => None
+            };
+            if visibility.is_some() {
+                self.next_token();
+            }
+            let visibility = visibility.unwrap_or(Visibility::Protected);
+
+            let member = self.expect_name("a field or method")?;
+            if member.value == Symbol::CONSTRUCT && self.eat(TokenType::LParen) {
+                let args = self.parse_arguments()?;
+                let function = self.parse_function_body(member, args, Type::void())?;
+                constructors.push(Method { visibility, function, span: start.to(self.previous_span()) });
+                continue;
+            }
+
+            self.expect(TokenType::Colon, &format!("':' after identifier '{}'", member.value))?;
+            // parameters without names can only be a field's function type
+            let named = self.peek_nth(1).token_type != TokenType::LParen
+                || self.peek_nth(2).token_type == TokenType::RParen
+                || (self.peek_nth(2).token_type == TokenType::Identifier && self.peek_nth(3).token_type == TokenType::Colon);
+            if self.at(TokenType::Keyword(Keyword::Func)) && named {
+                self.next_token();
+                let (args, return_type) = self.parse_signature()?;
+                // without a body it's a field holding a function
+                if self.eat(TokenType::Semicolon) {
+                    fields.push(Field {
+                        name: member.value,
+                        visibility,
+                        field_type: Type::Function {
+                            params: args.into_iter().map(|arg| arg.1).collect(),
+                            return_type: Box::new(return_type)
+                        },
+                        span: start.to(self.previous_span())
+                    });
+                    continue;
+                }
+
+                let function = self.parse_function_body(member, args, return_type)?;
+                methods.push(Method { visibility, function, span: start.to(self.previous_span()) });
+            } else {
+                let field_type = self.parse_type()?;
+                self.expect(TokenType::Semicolon, &format!("';' after field '{}'", member.value))?;
+                fields.push(Field {
+                    name: member.value,
+                    visibility,
+                    field_type,
+                    span: start.to(self.previous_span())
+                });
+            }
+        }
+
+        let close = self.expect(TokenType::RBrace, "'}' to close the struct")?;
+        Ok(AST::StructDefinition {
+            name: name.value,
+            fields,
+            constructors,
+            methods,
+            span: name.span.to(close.span)
+        })
+    }
+
+    // the '(' has already been consumed
+    fn parse_arguments(&mut self) -> ParseResult<Vec<Pair<Symbol, Type>>> {
+        let mut args = Vec::new();
+        if self.eat(TokenType::RParen) {
+            return Ok(args);
+        }
+
+        loop {
+            let name = self.expect_name("an argument")?;
+            self.expect(TokenType::Colon, &format!("':' after identifier '{}'", name.value))?;
+            args.push(Pair(name.value, self.parse_type()?));
+
+            if self.eat(TokenType::RParen) {
+                return Ok(args);
+            }
+            self.expect(TokenType::Comma, &format!("',' or ')' after argument '{}'", name.value))?;
+        }
+    }
+
+    // name, name<argument>, Array<element>, func(params): return_type, *pointee or &referent
+    fn parse_type(&mut self) -> ParseResult<Type> {
+        if self.eat(TokenType::Star) {
+            return Ok(Type::Pointer(Box::new(self.parse_type()?)));
+        }
+        if self.eat(TokenType::Ampersand) {
+            return Ok(Type::Reference(Box::new(self.parse_type()?)));
+        }
+        if self.eat(TokenType::Keyword(Keyword::Func)) {
+            return self.parse_function_type();
+        }
+
+        let name = self.expect_name("a type")?;
+        if !self.eat(TokenType::LAngle) {
+            return Ok(Type::Named(name.value));
+        }
+
+        let element = self.parse_type()?;
+        // an Array has exactly one element type
+        if name.value == Symbol::ARRAY {
+            self.expect_closing_angle("'>' after the element type of 'Array'")?;
+            return Ok(Type::Array(Box::new(element)));
+        }
+
+        let mut args = vec![element];
+        while self.eat(TokenType::Comma) {
+            args.push(self.parse_type()?);
+        }
+        self.expect_closing_angle("',' or '>' after type argument")?;
+
+        Ok(Type::Generic {
+            name: name.value,
+            args
+        })
+    }
+
+    // the '>' closing type arguments, which the lexer may have joined with what
+    // follows into '>>', '>=' or '>>='. The rest is handed out as its own token.
+    fn expect_closing_angle(&mut self, what: &str) -> ParseResult<()> {
+        let token = self.peek_token();
+        let rest = match token.token_type {
+            TokenType::RAngle => None,
+            TokenType::ShiftRight => Some(TokenType::RAngle),
+            TokenType::GreaterEqual => Some(TokenType::Equal),
+            TokenType::ShiftRightEqual => Some(TokenType::GreaterEqual),
+            _ => return Err(Diagnostic::error(ErrorCode::UnexpectedToken, format!("Expected {} but got '{}'", what, self.describe(token)), token.span).into())
         };
 
-        let mut current_node = AST::None;
+        let span = token.span;
+        self.next_token();
+        if let Some(token_type) = rest {
+            self.split_token = Some(Token::new(token_type, Symbol::EMPTY, Span::new(span.start + 1, span.end, span.line, span.column + 1, span.byte_column + 1)));
+        }
+        Ok(())
+    }
 
-        let mut token = self.lexer.next();
-        while token.token_type != TokenType::EOF {
-            match token.token_type {
-                TokenType::Identifier => {
-                    let colon = self.lexer.next();
-                    if colon.token_type != TokenType::Colon {
-                        self.error_with_string(colon.line, colon.char_pos, format!("Expected ':' after identifier '{}' but got '{}'", token.value, colon.value));
-                        break;
-                    }
+    // the signature after 'func' as in a function declaration, except that
+    // parameters may go without names as in `func(string): void`
+    fn parse_function_type(&mut self) -> ParseResult<Type> {
+        let mut params = Vec::new();
+        if self.eat(TokenType::LParen) {
+            while !self.at(TokenType::RParen) {
+                if self.at(TokenType::Identifier) && self.peek_nth(1).token_type == TokenType::Colon {
+                    self.next_token();
+                    self.next_token();
+                }
+                params.push(self.parse_type()?);
+                if !self.eat(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.expect(TokenType::RParen, "')' after the parameter types")?;
+        }
+
+        Ok(Type::Function {
+            params,
+            return_type: Box::new(self.parse_return_type()?)
+        })
+    }
+
+    // parses statements up to (not including) the closing '}', an expression
+    // right before it without a ';' is returned separately as the block's value
+    fn parse_body(&mut self) -> (Vec<AST>, Option<AST>) {
+        let mut body = Vec::new();
+        let mut value = None;
+        let depth = self.depth();
+
+        while !self.at(TokenType::RBrace) && !self.at(TokenType::Eof) {
+            // empty statement
+            if self.eat(TokenType::Semicolon) {
+                continue;
+            }
 
-                    let typename = self.lexer.next();
-
-                    if typename.value == "func" {
-                        let mut args: Vec<Pair<String, Type>> = Vec::new();
-                        let mut body = Vec::new();
-
-                        let mut next = self.lexer.next();
-
-                        let mut return_type = "void".to_string();
-
-                        // parse arguments
-                        match next.token_type {
-                            TokenType::LParen => {
-                                next = self.lexer.next();
-                                if next.token_type != TokenType::RParen {
-                                    if next.token_type == TokenType::Identifier {
-                                        let mut arg_pair = Pair {
-                                            0: next.value.clone(),
-                                            1: Type {
-                                                name: "".to_string(),
-                                                subtype: None,
-                                            },
-                                        };
-
-                                        let mut index = 0;
-
-
-                                        println!("{:?}", next);
-
-                                        while next.token_type != TokenType::RParen {
-                                            if next.token_type == TokenType::Comma {
-                                                index = 0;
-                                                args.push(arg_pair.clone());
-                                                next = self.lexer.next();
-                                            }
-
-                                            if index == 1 {
-                                                if next.token_type == TokenType::Colon {
-                                                    next = self.lexer.next();
-                                                } else {
-                                                    self.error_with_string(next.line, next.char_pos,
-                                                                           format!("Expected ':' after identifier '{}' but got '{}'", arg_pair.0, next.value));
-                                                }
-
-
-                                                let peek = self.lexer.peek();
-
-                                                if next.token_type == TokenType::Identifier {
-                                                    // not acounting nested subtypes
-                                                    if peek.token_type == TokenType::LAngle {
-                                                        arg_pair.1.name = next.value.clone();
-
-                                                        self.lexer.next();
-                                                        next = self.lexer.next();
-                                                        self.lexer.next();
-
-                                                        let subtype = Some(Box::new(Type {
-                                                            name: next.value.clone(),
-                                                            subtype: None,
-                                                        }));
-                                                        arg_pair.1.subtype = subtype;
-                                                    } else {
-                                                        arg_pair.1 = Type {
-                                                            name: next.value.clone(),
-                                                            subtype: None,
-                                                        };
-                                                    }
-                                                } else {
-                                                    self.error_with_string(next.line, next.char_pos,
-                                                                           format!("Expected identifier after ',' in argument list got '{}'", next.value));
-                                                    break;
-                                                }
-                                            } else if index == 0 {
-                                                arg_pair.0 = next.value.clone();
-                                            } else {
-                                                self.error_with_string(next.line, next.char_pos, format!("Expected ',' after argument '{:?}'", arg_pair.1));
-                                                break;
-                                            }
-
-                                            index += 1;
-                                            next = self.lexer.next();
-                                        }
-
-                                        if index != 2 {
-                                            self.error(next.line, next.char_pos, "The argument list has a wrong format");
-                                            break;
-                                        }
-
-                                        args.push(arg_pair.clone());
-
-                                        // should be unreachable but just in case
-                                        if next.token_type != TokenType::RParen {
-                                            self.error_with_string(next.line, next.char_pos, format!("Expected ')' after params but got '{}'", next.value));
-                                            break;
-                                        }
-                                    } else {
-                                        self.error_with_string(next.line, next.char_pos, format!("Expected identifier after '(' but got '{}'", next.value));
-                                        break;
-                                    }
-                                }
-                            }
-                            _ => {
-                                // TODO functions without arguments
-                                self.error(next.line, next.char_pos, "Expected '(' after 'func'");
-                            }
-                        }
-
-                        // get return type
-                        next = self.lexer.next();
-                        if next.token_type == TokenType::Colon {
-                            next = self.lexer.next();
-                            return_type = next.value;
-                        }
-
-                        next = self.lexer.next();
-                        if next.token_type != TokenType::LBrace {
-                            self.error_with_string(next.line, next.char_pos, format!("Expected '{}' after return type", return_type));
-                            break;
-                        }
-
-                        // parse body
-                        next = self.lexer.next();
-                        while next.token_type != TokenType::RBrace {
-                            match next.token_type {
-                                TokenType::Identifier => {
-                                    if next.value == "return" {
-                                        let return_value = self.lexer.next();
-                                        if return_value.token_type != TokenType::Semicolon {
-                                            body.push(AST::Return {
-                                                value: Box::new(AST::Value { value: return_value.value })
-                                            });
-                                            next = self.lexer.next();
-                                            break;
-                                        } else {
-                                            if return_type != "void" {
-                                                self.error(return_value.line, return_value.char_pos, "Expected return value after 'return'");
-                                                break;
-                                            }
-                                            body.push(AST::Return {
-                                                value: Box::new(AST::Value {
-                                                    value: "".to_string()
-                                                })
-                                            });
-                                            next = self.lexer.next();
-                                            break;
-                                        }
-                                    }
-                                    self.error(next.line, next.char_pos, "Not implemented");
-                                }
-                                _ => {}
-                            }
-
-                            next = self.lexer.next();
-                        }
-
-                        if next.token_type != TokenType::RBrace {
-                            self.error(next.line, next.char_pos, "Not closing function.");
-                            break;
-                        }
-
-                        current_node = AST::FunctionDefinition {
-                            name: token.value.clone(),
-                            args,
-                            body,
-                            return_type,
-                        };
-
-                        match file {
-                            AST::File { mut child, filename } => {
-                                child.push(current_node);
-                                file = AST::File {
-                                    child,
-                                    filename,
-                                };
-                            }
-                            _ => {
-                                panic!("Unreachable");
-                            }
-                        }
+            match self.parse_statement() {
+                Ok((statement, terminated)) => {
+                    if !terminated && self.at(TokenType::RBrace) && Self::is_expression(&statement) {
+                        value = Some(statement);
+                    } else {
+                        body.push(statement);
                     }
                 }
-                _ => {
-                    self.error_with_string(token.line, token.char_pos, format!("Unexpected token: {:?}", token.token_type));
+                Err(diagnostic) => {
+                    self.report(*diagnostic);
+                    self.synchronize_statement(depth);
                 }
             }
-            token = self.lexer.next();
         }
 
-        file
+        (body, value)
     }
 
-    fn error(&self, line: i32, char_pos: i32, msg: &str) {
-        eprintln!("[Parser] Error at {line}:{char_pos}: {}", msg);
-        panic!();
+    fn is_expression(node: &AST) -> bool {
+        !matches!(node, AST::Return { .. } | AST::VariableDeclaration { .. } | AST::Assign { .. } |
+            AST::While { .. } | AST::For { .. } | AST::Break { .. } | AST::Continue { .. })
     }
 
-    fn error_with_string(&self, line: i32, char_pos: i32, msg: String) {
-        eprintln!("[Parser] Error at {line}:{char_pos}: {}", msg);
-        panic!();
+    // { body }
+    fn parse_block(&mut self) -> ParseResult<AST> {
+        let open = self.expect(TokenType::LBrace, "'{'")?;
+        let (body, value) = self.parse_body();
+        let close = self.expect(TokenType::RBrace, "'}' to close the block")?;
+
+        Ok(AST::Block {
+            body,
+            value: value.map(Box::new),
+            span: open.span.to(close.span)
+        })
     }
 
-    fn name_with_file_from_ast(file: AST, name: String) -> String {
-        match file {
-            AST::File { child, filename } => {
-                let mut name = filename.clone();
-                name = name.replace("/", "_");
-                name = name.replace(".", "_");
-                name.push_str("__");
-                name.push_str(&name.clone());
-                name.push_str("__");
-                name
+    // a statement ends with ';', which may be left out before the closing '}'
+    // and after expressions ending in a block. Returns whether a ';' was read.
+    fn parse_statement(&mut self) -> ParseResult<(AST, bool)> {
+        let statement = match self.peek_token().token_type {
+            TokenType::Keyword(Keyword::Return) => self.parse_return()?,
+            TokenType::Keyword(Keyword::Val | Keyword::Var) => self.parse_variable_declaration()?,
+            TokenType::Keyword(Keyword::While | Keyword::For) => self.parse_loop(None)?,
+            TokenType::Identifier if self.peek_nth(1).token_type == TokenType::Colon
+                && matches!(self.peek_nth(2).token_type, TokenType::Keyword(Keyword::While | Keyword::For)) => {
+                let label = self.next_token();
+                self.next_token();
+                self.parse_loop(Some(label))?
             }
-            _ => {
-                panic!("Unreachable");
+            TokenType::Keyword(Keyword::Break | Keyword::Continue) => self.parse_jump()?,
+            _ => self.parse_expression_statement()?
+        };
+
+        if self.eat(TokenType::Semicolon) {
+            return Ok((statement, true));
+        }
+        if !self.at(TokenType::RBrace) && !statement.ends_with_block() {
+            self.expect(TokenType::Semicolon, "';' after the statement")?;
+        }
+
+        Ok((statement, false))
+    }
+
+    // return [value]
+    fn parse_return(&mut self) -> ParseResult<AST> {
+        let keyword = self.next_token();
+
+        if self.at(TokenType::Semicolon) || self.at(TokenType::RBrace) {
+            if !self.return_type.is_void() {
+                let span = self.peek_token().span;
+                return Err(Diagnostic::error(ErrorCode::MissingReturnValue, "Expected return value after 'return'", span).into());
             }
+
+            return Ok(AST::Return {
+                value: Box::new(AST::None { span: keyword.span }),
+                span: keyword.span
+            });
         }
+
+        let value = self.parse_expression()?;
+        Ok(AST::Return {
+            span: keyword.span.to(value.span()),
+            value: Box::new(value)
+        })
     }
-    fn name_with_file(filename: String, name: String) -> String {
-        let mut name = filename.clone();
-        name = name.replace("/", "_");
-        name = name.replace(".", "_");
-        name.push_str("__");
-        name.push_str(&name.clone());
-        name.push_str("__");
-        name
+
+    // while condition { }, for variable in iterable { }, `label` is the name
+    // before the ':' of a labeled loop
+    fn parse_loop(&mut self, label: Option<Token>) -> ParseResult<AST> {
+        let keyword = self.next_token();
+        let start = label.as_ref().map_or(keyword.span, |label| label.span);
+        let label = label.map(|label| label.value);
+
+        if keyword.token_type == TokenType::Keyword(Keyword::While) {
+            let condition = self.with_struct_literals(false, Self::parse_expression)?;
+            let body = self.parse_block()?;
+            return Ok(AST::While {
+                label,
+                condition: Box::new(condition),
+                span: start.to(body.span()),
+                body: Box::new(body)
+            });
+        }
+
+        let variable = self.expect_name("the loop variable")?;
+        self.expect(TokenType::Keyword(Keyword::In), "'in' after the loop variable")?;
+        let mut iterable = self.with_struct_literals(false, Self::parse_expression)?;
+        if self.at(TokenType::DotDot) || self.at(TokenType::DotDotEqual) {
+            let inclusive = self.next_token().token_type == TokenType::DotDotEqual;
+            let end = self.with_struct_literals(false, Self::parse_expression)?;
+            iterable = AST::Range {
+                span: iterable.span().to(end.span()),
+                start: Box::new(iterable),
+                end: Box::new(end),
+                inclusive
+            };
+        }
+        let body = self.parse_block()?;
+
+        Ok(AST::For {
+            label,
+            variable: variable.value,
+            iterable: Box::new(iterable),
+            span: start.to(body.span()),
+            body: Box::new(body)
+        })
     }
 
-    pub fn print_debug_pseudo_asm(ast: AST, mut file: File) {
-        match ast {
-            AST::File { child, filename } => {
-                for node in child {
-                    match node {
-                        AST::FunctionDefinition { name, args, body, return_type } => {
-                            file.write(format!("{}:\n", Self::name_with_file(filename.clone(), name.clone())).as_bytes());
-                            file.write(format!("\tpush rbp\n").as_bytes());
-                            file.write(format!("\tmov rbp, rsp\n").as_bytes());
-                            file.write(format!("\tsub rsp, {}\n", args.len() * 8).as_bytes());
-                            for (index, arg) in args.iter().enumerate() {
-                                file.write(format!("\tmov [rbp - {}], {}\n", index * 8, arg.0).as_bytes());
-                            }
-                            for node in body {
-                                match node {
-                                    AST::Return { value } => {
-                                        match *value {
-                                            AST::Value { value } => {
-                                                file.write(format!("\tmov rax, {}\n", value).as_bytes());
-                                                file.write(format!("\tmov rsp, rbp\n").as_bytes());
-                                                file.write(format!("\tpop rbp\n").as_bytes());
-                                                file.write(format!("\tret\n").as_bytes());
-                                            }
-                                            _ => {
-                                                panic!("Unreachable");
-                                            }
-                                        }
-                                    }
-                                    _ => {
-                                        panic!("Unreachable");
-                                    }
-                                }
-                            }
-                        }
-                        _ => {
-                            unimplemented!();
-                        }
-                    }
+    // break [label], continue [label]
+    fn parse_jump(&mut self) -> ParseResult<AST> {
+        let keyword = self.next_token();
+        let mut span = keyword.span;
+        let mut label = None;
+        if self.at(TokenType::Identifier) {
+            let name = self.next_token();
+            span = span.to(name.span);
+            label = Some(name.value);
+        }
+
+        Ok(if keyword.token_type == TokenType::Keyword(Keyword::Break) {
+            AST::Break { label, span }
+        } else {
+            AST::Continue { label, span }
+        })
+    }
+
+    // val name[: Type] = value, or var for a mutable variable
+    fn parse_variable_declaration(&mut self) -> ParseResult<AST> {
+        let keyword = self.next_token();
+        let name = self.expect_name("a variable")?;
+
+        let mut variable_type = None;
+        if self.eat(TokenType::Colon) {
+            variable_type = Some(self.parse_type()?);
+        }
+
+        self.expect(TokenType::Equal, &format!("'=' after variable '{}'", name.value))?;
+        let value = self.parse_expression()?;
+
+        Ok(AST::VariableDeclaration {
+            name: name.value,
+            mutable: keyword.token_type == TokenType::Keyword(Keyword::Var),
+            variable_type,
+            span: keyword.span.to(value.span()),
+            value: Box::new(value)
+        })
+    }
+
+    // an expression, or an assignment to one
+    fn parse_expression_statement(&mut self) -> ParseResult<AST> {
+        let expression = self.parse_expression()?;
+
+        let token = self.peek_token();
+        let operator = match token.token_type {
+            TokenType::Equal => None,
+            ref token_type => match compound_assignment(token_type) {
+                Some(operator) => Some(operator),
+                None => return Ok(expression)
+            }
+        };
+
+        if !matches!(expression, AST::Identifier { .. } | AST::Field { .. } | AST::Index { .. }) {
+            return Err(Diagnostic::error(ErrorCode::InvalidAssignmentTarget, "Invalid left-hand side of assignment", expression.span())
+                .with_primary_label("cannot assign to this expression")
+                .with_help("only variables, fields and indexed elements can be assigned to").into());
+        }
+        self.next_token();
+
+        let value = self.parse_expression()?;
+        Ok(AST::Assign {
+            span: expression.span().to(value.span()),
+            target: Box::new(expression),
+            operator,
+            value: Box::new(value)
+        })
+    }
+
+    pub fn parse_expression(&mut self) -> ParseResult<AST> {
+        self.parse_binary(1)
+    }
+
+    // precedence climbing: operators binding looser than `min_precedence` are
+    // left for the caller
+    fn parse_binary(&mut self, min_precedence: u8) -> ParseResult<AST> {
+        let left = self.parse_unary()?;
+        self.parse_binary_rest(left, min_precedence)
+    }
+
+    // continues a binary expression whose left operand has been parsed
+    fn parse_binary_rest(&mut self, mut left: AST, min_precedence: u8) -> ParseResult<AST> {
+        while let Some(operator) = BinaryOperator::from_token(&self.peek_token().token_type) {
+            let precedence = operator.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            let operator_token = self.next_token();
+
+            let right = self.parse_binary(precedence + 1)?;
+            let next = self.peek_token();
+            if operator.is_comparison() && BinaryOperator::from_token(&next.token_type).is_some_and(|next| next.is_comparison()) {
+                return Err(Diagnostic::error(ErrorCode::ChainedComparison, "Comparison operators cannot be chained", next.span)
+                    .with_label(operator_token.span, "first comparison")
+                    .with_help("split the comparison with '&&', e.g. 'a < b && b < c'").into());
+            }
+
+            let span = left.span().to(right.span());
+            left = AST::Binary {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+                span
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<AST> {
+        let Some(operator) = UnaryOperator::from_token(&self.peek_token().token_type) else {
+            return self.parse_postfix();
+        };
+        let token = self.next_token();
+
+        // the operand may contain anything binding tighter than a unary operator
+        let operand = self.parse_binary(UNARY_PRECEDENCE)?;
+        Ok(AST::Unary {
+            operator,
+            span: token.span.to(operand.span()),
+            operand: Box::new(operand)
+        })
+    }
+
+    // calls, field accesses and indexing following a primary expression
+    fn parse_postfix(&mut self) -> ParseResult<AST> {
+        let mut expression = self.parse_primary()?;
+
+        loop {
+            let token = self.peek_token();
+            match token.token_type {
+                TokenType::LParen => {
+                    self.next_token();
+                    let args = self.parse_call_arguments()?;
+                    let close = self.expect(TokenType::RParen, "')' after call arguments")?;
+                    expression = AST::Call {
+                        span: expression.span().to(close.span),
+                        callee: Box::new(expression),
+                        args
+                    };
+                }
+                TokenType::Dot => {
+                    self.next_token();
+                    let field = self.expect_name("a field")?;
+                    expression = AST::Field {
+                        span: expression.span().to(field.span),
+                        object: Box::new(expression),
+                        field: field.value
+                    };
+                }
+                TokenType::LBracket => {
+                    self.next_token();
+                    let index = self.parse_expression()?;
+                    let close = self.expect(TokenType::RBracket, "']' after index")?;
+                    expression = AST::Index {
+                        span: expression.span().to(close.span),
+                        object: Box::new(expression),
+                        index: Box::new(index)
+                    };
+                }
+                _ => return Ok(expression)
+            }
+        }
+    }
+
+    // if condition { } else if condition { } else { }
+    // if scrutinee == { pattern -> value; ... }
+    fn parse_if(&mut self) -> ParseResult<AST> {
+        let keyword = self.next_token();
+
+        // anything binding tighter than a comparison, then look for `== {`
+        let scrutinee = self.with_struct_literals(false, |parser| parser.parse_binary(BinaryOperator::Equal.precedence() + 1))?;
+        let operator = BinaryOperator::from_token(&self.peek_token().token_type).filter(|operator| operator.is_comparison());
+        if let Some(operator) = operator.filter(|_| self.peek_nth(1).token_type == TokenType::LBrace) {
+            self.next_token();
+            let (arms, close) = self.parse_arms()?;
+            return Ok(AST::IfCompare {
+                scrutinee: Box::new(scrutinee),
+                operator,
+                arms,
+                span: keyword.span.to(close)
+            });
+        }
+
+        let condition = self.with_struct_literals(false, |parser| parser.parse_binary_rest(scrutinee, 1))?;
+        let then_branch = self.parse_block()?;
+
+        let mut else_branch = None;
+        if self.eat(TokenType::Keyword(Keyword::Else)) {
+            else_branch = Some(Box::new(if self.at(TokenType::Keyword(Keyword::If)) {
+                self.parse_if()?
+            } else {
+                self.parse_block()?
+            }));
+        }
+
+        let end = else_branch.as_ref().map_or(then_branch.span(), |branch| branch.span());
+        Ok(AST::If {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch,
+            span: keyword.span.to(end)
+        })
+    }
+
+    // switch scrutinee { pattern -> value; ... }
+    fn parse_switch(&mut self) -> ParseResult<AST> {
+        let keyword = self.next_token();
+        let scrutinee = self.with_struct_literals(false, Self::parse_expression)?;
+        let (arms, close) = self.parse_arms()?;
+
+        Ok(AST::Switch {
+            scrutinee: Box::new(scrutinee),
+            arms,
+            span: keyword.span.to(close)
+        })
+    }
+
+    // { pattern -> value; ... }, returns the arms and the span of the '}'
+    fn parse_arms(&mut self) -> ParseResult<(Vec<Arm>, Span)> {
+        self.expect(TokenType::LBrace, "'{' to start the arms")?;
+
+        let mut arms = Vec::new();
+        while !self.at(TokenType::RBrace) && !self.at(TokenType::Eof) {
+            let start = self.peek_token().span;
+            let pattern = if self.at(TokenType::Underscore) && self.peek_nth(1).token_type == TokenType::Arrow {
+                self.next_token();
+                None
+            } else {
+                Some(self.parse_expression()?)
+            };
+
+            self.expect(TokenType::Arrow, "'->' after the pattern")?;
+            let value = self.parse_expression()?;
+            arms.push(Arm {
+                pattern,
+                value,
+                span: start.to(self.previous_span())
+            });
+
+            if !self.at(TokenType::RBrace) && !arms.last().unwrap().value.ends_with_block() {
+                self.expect(TokenType::Semicolon, "';' after the arm")?;
+            } else {
+                self.eat(TokenType::Semicolon);
+            }
+        }
+
+        let close = self.expect(TokenType::RBrace, "'}' to close the arms")?;
+        Ok((arms, close.span))
+    }
+
+    // name { } or name { field: ...
+    fn at_struct_literal(&self) -> bool {
+        !self.no_struct_literal && self.peek_nth(1).token_type == TokenType::LBrace && match self.peek_nth(2).token_type {
+            TokenType::RBrace => true,
+            TokenType::Identifier => self.peek_nth(3).token_type == TokenType::Colon,
+            _ => false
+        }
+    }
+
+    // name { field: value, ... }, a trailing comma is allowed
+    fn parse_struct_literal(&mut self) -> ParseResult<AST> {
+        let name = self.next_token();
+        self.next_token();
+
+        let mut fields = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            let field = self.expect_name("a field")?;
+            self.expect(TokenType::Colon, &format!("':' after field '{}'", field.value))?;
+            fields.push(Pair(field.value, self.with_struct_literals(true, Self::parse_expression)?));
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+
+        let close = self.expect(TokenType::RBrace, &format!("'}}' to close the '{}' literal", name.value))?;
+        Ok(AST::StructLiteral {
+            name: name.value,
+            fields,
+            span: name.span.to(close.span)
+        })
+    }
+
+    // parses with struct literals allowed or not, then restores the previous setting
+    fn with_struct_literals<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        let previous = std::mem::replace(&mut self.no_struct_literal, !allowed);
+        let result = parse(self);
+        self.no_struct_literal = previous;
+        result
+    }
+
+    // up to (not including) the closing ')', a trailing comma is allowed
+    fn parse_call_arguments(&mut self) -> ParseResult<Vec<AST>> {
+        let mut args = Vec::new();
+
+        while !self.at(TokenType::RParen) {
+            args.push(self.with_struct_literals(true, Self::parse_expression)?);
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+
+        Ok(args)
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<AST> {
+        let token = self.peek_token();
+        match token.token_type {
+            TokenType::Number | TokenType::String | TokenType::Char => {
+                let token = self.next_token();
+                // the lexer only hands out literal tokens it could decode
+                Ok(AST::Literal {
+                    value: token.literal.unwrap(),
+                    span: token.span
+                })
+            }
+            TokenType::Identifier if self.at_struct_literal() => self.parse_struct_literal(),
+            TokenType::Identifier | TokenType::Keyword(Keyword::SelfValue) => {
+                let token = self.next_token();
+                Ok(AST::Identifier {
+                    name: token.value,
+                    span: token.span
+                })
+            }
+            TokenType::Keyword(Keyword::If) => self.parse_if(),
+            TokenType::Keyword(Keyword::Switch) => self.parse_switch(),
+            TokenType::LBrace => self.parse_block(),
+            // the placeholder for "no value"
+            TokenType::Underscore => {
+                let token = self.next_token();
+                Ok(AST::None { span: token.span })
+            }
+            TokenType::LParen => {
+                self.next_token();
+                let expression = self.with_struct_literals(true, Self::parse_expression)?;
+                self.expect(TokenType::RParen, "')' to close the parenthesized expression")?;
+                Ok(expression)
+            }
+            _ => Err(Diagnostic::error(ErrorCode::ExpectedExpression, format!("Expected expression but got '{}'", self.describe(token)), token.span).into())
+        }
+    }
+
+    // skips past the ';' ending the current statement, or up to the '}' ending the
+    // block whose statements are at `depth`
+    fn synchronize_statement(&mut self, depth: u32) {
+        loop {
+            let token = self.peek_token();
+            match token.token_type {
+                TokenType::Eof => return,
+                TokenType::RBrace if self.depth() < depth => return,
+                TokenType::Semicolon if self.depth() == depth => {
+                    self.next_token();
+                    return;
+                }
+                _ => {
+                    self.next_token();
                 }
             }
-            _ => {
-                panic!("You can only generate pseudo-asm for files");
+        }
+    }
+
+    // the lexer already reported text it had to skip, errors right after it
+    // are most likely caused by the missing token
+    fn report(&mut self, diagnostic: Diagnostic) {
+        let tokens = self.tree.tokens();
+        let index = tokens.partition_point(|token| token.span.start < diagnostic.primary.span.start);
+        let skipped = |trivia: &Vec<Trivia>| trivia.iter().any(|trivia| trivia.kind == TriviaKind::Skipped);
+
+        let after_skipped = tokens.get(index).is_some_and(|token| skipped(&token.leading_trivia))
+            || (index > 0 && skipped(&tokens[index - 1].trailing_trivia));
+        if !after_skipped {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    // skips to the next top-level `name:`, a declaration at the start of a line
+    // also counts so an unclosed group doesn't swallow the rest of the file
+    fn synchronize_item(&mut self) {
+        loop {
+            let token = self.peek_token();
+            if token.token_type == TokenType::Eof {
+                return;
+            }
+
+            self.next_token();
+            let top_level = self.depth() == 0 || self.peek_token().span.column == 1;
+            if top_level && self.at_item_start() {
+                return;
             }
         }
     }
-}
\ No newline at end of file
+
+    fn at_item_start(&self) -> bool {
+        matches!(self.peek_token().token_type, TokenType::Identifier | TokenType::Keyword(_))
+            && self.peek_nth(1).token_type == TokenType::Colon
+    }
+
+    // nesting depth of the next token
+    fn depth(&self) -> u32 {
+        self.depths[self.skip_doc_comments(self.position)]
+    }
+
+    // consumes the next token if it has the given type, `what` describes it for the error
+    fn expect(&mut self, token_type: TokenType, what: &str) -> ParseResult<Token> {
+        let token = self.peek_token();
+        if token.token_type != token_type {
+            return Err(Diagnostic::error(ErrorCode::UnexpectedToken, format!("Expected {} but got '{}'", what, self.describe(token)), token.span).into());
+        }
+
+        Ok(self.next_token())
+    }
+
+    fn expect_name(&mut self, what: &str) -> ParseResult<Token> {
+        let token = self.peek_token();
+        match token.token_type {
+            TokenType::Identifier => Ok(self.next_token()),
+            TokenType::Keyword(keyword) => Err(Self::keyword_as_name_error(token.span, keyword)),
+            _ => Err(Diagnostic::error(ErrorCode::UnexpectedToken, format!("Expected identifier for {} but got '{}'", what, self.describe(token)), token.span).into())
+        }
+    }
+
+    fn describe(&self, token: &Token) -> String {
+        match token.token_type {
+            TokenType::Eof => "end of file".to_string(),
+            _ => self.tree.token_text(token).to_string()
+        }
+    }
+
+    fn at(&self, token_type: TokenType) -> bool {
+        self.peek_token().token_type == token_type
+    }
+
+    fn eat(&mut self, token_type: TokenType) -> bool {
+        if self.at(token_type) {
+            self.next_token();
+            return true;
+        }
+
+        false
+    }
+
+    // doc comments are only of interest to tooling for now
+    fn skip_doc_comments(&self, mut index: usize) -> usize {
+        let tokens = self.tree.tokens();
+        while index < tokens.len() - 1 && tokens[index].token_type == TokenType::DocComment {
+            index += 1;
+        }
+
+        index
+    }
+
+    fn next_token(&mut self) -> Token {
+        if let Some(token) = self.split_token.take() {
+            return token;
+        }
+        self.position = self.skip_doc_comments(self.position);
+
+        // the tree always ends with Eof, which is handed out repeatedly
+        let token = self.tree.token(self.position).clone();
+        if self.position < self.tree.tokens().len() - 1 {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    // the last token handed out by next_token
+    fn previous_span(&self) -> Span {
+        let tokens = self.tree.tokens();
+        let span = tokens[..self.position].iter().rev()
+            .find(|token| token.token_type != TokenType::DocComment)
+            .map_or(Span::default(), |token| token.span);
+
+        // only the '>' of a split token has been handed out
+        match &self.split_token {
+            Some(rest) => Span { end: rest.span.start, ..span },
+            None => span
+        }
+    }
+
+    fn peek_token(&self) -> &Token {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, mut k: usize) -> &Token {
+        if let Some(rest) = &self.split_token {
+            if k == 0 {
+                return rest;
+            }
+            k -= 1;
+        }
+
+        let mut index = self.skip_doc_comments(self.position);
+        for _ in 0..k {
+            index = self.skip_doc_comments((index + 1).min(self.tree.tokens().len() - 1));
+        }
+
+        self.tree.token(index)
+    }
+
+    fn keyword_as_name_error(span: Span, keyword: Keyword) -> Box<Diagnostic> {
+        Diagnostic::error(ErrorCode::KeywordAsName, format!("'{}' is a reserved keyword and cannot be used as a name", keyword), span)
+            .with_help(format!("pick another name, e.g. '{}_'", keyword))
+            .into()
+    }
+
+    pub fn print_debug_pseudo_asm(ast: AST, receivers: Receivers, file: File) -> io::Result<()> {
+        CodeGen::new(file, receivers).generate(ast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> (AST, Diagnostics) {
+        let mut parser = Parser::new(Lexer::new(source.to_string()));
+        let ast = parser.parse("test.dust".to_string());
+        (ast, parser.take_diagnostics())
+    }
+
+    fn codes(source: &str) -> Vec<ErrorCode> {
+        parse(source).1.iter().map(|diagnostic| diagnostic.code).collect()
+    }
+
+    fn item_names(ast: &AST) -> Vec<String> {
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        child.iter().map(|item| match item {
+            AST::FunctionDefinition { name, .. } | AST::StructDefinition { name, .. } => name.to_string(),
+            item => panic!("unexpected item {:?}", item)
+        }).collect()
+    }
+
+    // the expression in `f: func -> int32 { return <source>; }`, fully parenthesised
+    fn expression(source: &str) -> String {
+        let (ast, diagnostics) = parse(&format!("f: func -> int32 {{ return {}; }}", source));
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let AST::FunctionDefinition { body, .. } = &child[0] else { panic!("expected a function") };
+        let AST::Return { value, .. } = &body[0] else { panic!("expected a return") };
+        shape(value)
+    }
+
+    fn shape(ast: &AST) -> String {
+        match ast {
+            AST::Identifier { name, .. } => name.to_string(),
+            AST::Literal { value: Literal::Int { value, .. }, .. } => value.to_string(),
+            AST::Unary { operator, operand, .. } => format!("({}{})", operator.as_str(), shape(operand)),
+            AST::Binary { operator, left, right, .. } => format!("({} {} {})", shape(left), operator.as_str(), shape(right)),
+            AST::Call { callee, args, .. } => {
+                let args = args.iter().map(shape).collect::<Vec<_>>();
+                format!("{}({})", shape(callee), args.join(", "))
+            }
+            AST::Field { object, field, .. } => format!("{}.{}", shape(object), field),
+            AST::Index { object, index, .. } => format!("{}[{}]", shape(object), shape(index)),
+            ast => panic!("unexpected expression {:?}", ast)
+        }
+    }
+
+    // the variant names of the statements in the body of the first function
+    fn statements(source: &str) -> Vec<String> {
+        let (ast, diagnostics) = parse(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let AST::FunctionDefinition { body, .. } = &child[0] else { panic!("expected a function") };
+        body.iter().map(|statement| format!("{:?}", statement).split([' ', '{']).next().unwrap().to_string()).collect()
+    }
+
+    // the argument and return types of every function in `source`
+    fn signatures(source: &str) -> Vec<(Vec<String>, String)> {
+        let (ast, diagnostics) = parse(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        child.iter().map(|item| match item {
+            AST::FunctionDefinition { args, return_type, .. } => (args.iter().map(|arg| arg.1.to_string()).collect(), return_type.to_string()),
+            item => panic!("unexpected item {:?}", item)
+        }).collect()
+    }
+
+    // `written` parsed as the type of an argument
+    fn parse_type(written: &str) -> Type {
+        let (ast, diagnostics) = parse(&format!("f: func(x: {}) {{ }}", written));
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let AST::FunctionDefinition { args, .. } = &child[0] else { panic!("expected a function") };
+        args[0].1.clone()
+    }
+
+    #[test]
+    fn keywords_cannot_be_names() {
+        assert_eq!(codes("return: func { }"), vec![ErrorCode::KeywordAsName]);
+        assert_eq!(codes("f: func(val: int32) { }"), vec![ErrorCode::KeywordAsName]);
+        assert_eq!(codes("f: func { val if = 1; }"), vec![ErrorCode::KeywordAsName]);
+    }
+
+    #[test]
+    fn errors_quote_the_source_text() {
+        let (_, diagnostics) = parse("f: func \"text\" { }");
+        let messages = diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect::<Vec<_>>();
+        assert_eq!(messages, vec!["Expected '{' after return type 'void' but got '\"text\"'"]);
+    }
+
+    #[test]
+    fn recovers_after_a_bad_statement() {
+        let (ast, diagnostics) = parse("f: func {\n    val = 1;\n    return 2 +;\n    val ok = 3;\n}\ng: func { }\n");
+        let codes = diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<_>>();
+        assert_eq!(codes, vec![ErrorCode::UnexpectedToken, ErrorCode::ExpectedExpression]);
+        assert_eq!(item_names(&ast), vec!["f", "g"]);
+
+        let AST::File { child, .. } = &ast else { unreachable!() };
+        let AST::FunctionDefinition { body, .. } = &child[0] else { unreachable!() };
+        assert!(matches!(body[..], [AST::VariableDeclaration { .. }]));
+    }
+
+    #[test]
+    fn recovers_at_the_next_declaration() {
+        let (ast, diagnostics) = parse("f: func( { ]\ng: fun { }\nh: func { }\n");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(item_names(&ast), vec!["h"]);
+    }
+
+    #[test]
+    fn errors_inside_nested_blocks_stay_there() {
+        let (ast, diagnostics) = parse("f: func {\n    if x { val = 1; }\n    return;\n}\n");
+        assert_eq!(diagnostics.len(), 1);
+        let AST::File { child, .. } = &ast else { unreachable!() };
+        let AST::FunctionDefinition { body, .. } = &child[0] else { unreachable!() };
+        assert!(matches!(body[..], [AST::If { .. }, AST::Return { .. }]));
+    }
+
+    #[test]
+    fn lex_errors_hide_the_errors_they_cause() {
+        let (_, diagnostics) = parse("f: func { val x = 1 ` 2; }");
+        let codes = diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<_>>();
+        assert_eq!(codes, vec![ErrorCode::UnknownCharacter]);
+    }
+
+    #[test]
+    fn unclosed_function() {
+        let (_, diagnostics) = parse("f: func {\n    return;\n");
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!(diagnostic.code, ErrorCode::UnclosedFunction);
+        assert_eq!(diagnostic.labels[0].message, "function 'f' starts here");
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(expression("a + b * c"), "(a + (b * c))");
+        assert_eq!(expression("a * b + c"), "((a * b) + c)");
+        assert_eq!(expression("a + b << c & d"), "(((a + b) << c) & d)");
+        assert_eq!(expression("a | b ^ c & d"), "(a | (b ^ (c & d)))");
+        assert_eq!(expression("a < b + 1 && c == d || e"), "(((a < (b + 1)) && (c == d)) || e)");
+        assert_eq!(expression("-a * !b"), "((-a) * (!b))");
+        assert_eq!(expression("-a.b[c]"), "(-a.b[c])");
+    }
+
+    #[test]
+    fn operators_associate_to_the_left() {
+        assert_eq!(expression("a - b - c"), "((a - b) - c)");
+        assert_eq!(expression("a / b * c % d"), "(((a / b) * c) % d)");
+        assert_eq!(expression("a << b >> c"), "((a << b) >> c)");
+        assert_eq!(expression("a || b || c"), "((a || b) || c)");
+        assert_eq!(expression("(a - (b - c))"), "(a - (b - c))");
+    }
+
+    #[test]
+    fn comparisons_cannot_be_chained() {
+        assert_eq!(codes("f: func -> bool { return a < b < c; }"), vec![ErrorCode::ChainedComparison]);
+        assert_eq!(codes("f: func -> bool { return a == b != c; }"), vec![ErrorCode::ChainedComparison]);
+        assert_eq!(expression("a < b && b < c"), "((a < b) && (b < c))");
+        assert_eq!(expression("(a < b) == c"), "((a < b) == c)");
+    }
+
+    #[test]
+    fn postfix_operators_chain() {
+        assert_eq!(expression("a.b.c"), "a.b.c");
+        assert_eq!(expression("a(1)(2, b)"), "a(1)(2, b)");
+        assert_eq!(expression("a.b(c)[1].d"), "a.b(c)[1].d");
+        assert_eq!(expression("a[b[1]](c.d)"), "a[b[1]](c.d)");
+        assert_eq!(expression("a[1 + b] * c()"), "(a[(1 + b)] * c())");
+    }
+
+    #[test]
+    fn returns_and_placeholders_have_positions() {
+        let (ast, _) = parse("f: func { return; }\ng: func -> int32 { return _; }");
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let returns = child.iter().map(|item| match item {
+            AST::FunctionDefinition { body, .. } => body[0].clone(),
+            item => panic!("unexpected item {:?}", item)
+        }).collect::<Vec<_>>();
+        let AST::Return { value, span } = &returns[0] else { panic!("expected a return") };
+        assert_eq!((span.start, span.end), (10, 16));
+        assert_eq!((value.span().start, value.span().end), (10, 16));
+        let AST::Return { value, span } = &returns[1] else { panic!("expected a return") };
+        assert_eq!((span.start, span.end), (39, 47));
+        assert_eq!((value.span().line, value.span().column), (2, 27));
+    }
+
+    #[test]
+    fn bodies_hold_statement_sequences() {
+        assert_eq!(
+            statements("f: func { g(); val x = 1; x = 2; x += 3; { g(); } return; }"),
+            vec!["Call", "VariableDeclaration", "Assign", "Assign", "Block", "Return"]
+        );
+        assert_eq!(statements("f: func { }"), Vec::<String>::new());
+        assert_eq!(statements("f: func { ; g(); }"), vec!["Call"]);
+    }
+
+    #[test]
+    fn returns_may_come_early() {
+        assert_eq!(statements("f: func -> int32 { return 1; g(); return 2; }"), vec!["Return", "Call", "Return"]);
+        assert_eq!(statements("f: func { if c { return; } g() }"), vec!["If", "Call"]);
+    }
+
+    #[test]
+    fn statements_end_with_a_semicolon_or_a_closing_brace() {
+        assert_eq!(statements("f: func { g() }"), vec!["Call"]);
+        assert_eq!(statements("f: func { { g() } if c { } while c { } g(); }"), vec!["Block", "If", "While", "Call"]);
+        assert_eq!(codes("f: func { g() h(); }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func { val x = 1 val y = 2; }"), vec![ErrorCode::UnexpectedToken]);
+    }
+
+    #[test]
+    fn loops_may_be_labeled() {
+        assert_eq!(statements("f: func(c: bool) { a: while c { } b: for i in 0..3 { } while c { break; } }"), vec!["While", "For", "While"]);
+        let (ast, _) = parse("f: func { a: for x in xs { continue a; } }");
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let AST::FunctionDefinition { body, .. } = &child[0] else { panic!("expected a function") };
+        let AST::For { label: Some(label), variable, span, .. } = &body[0] else { panic!("expected a labeled for loop") };
+        assert_eq!((label.as_str(), variable.as_str(), span.start), ("a", "x", 10));
+    }
+
+    #[test]
+    fn ranges_only_appear_in_for_loops() {
+        assert_eq!(statements("f: func { for i in 0..=10 { } }"), vec!["For"]);
+        assert_eq!(codes("f: func { val r = 0..10; }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func { a: g(); }"), vec![ErrorCode::UnexpectedToken]);
+    }
+
+    #[test]
+    fn function_types_name_parameters_optionally() {
+        let function = |params: Vec<Type>, return_type: Type| Type::Function { params, return_type: Box::new(return_type) };
+        assert_eq!(parse_type("func(s: string): void"), function(vec![Type::named("string")], Type::void()));
+        assert_eq!(parse_type("func(string, n: int32) -> bool"), function(vec![Type::named("string"), Type::named("int32")], Type::named("bool")));
+        assert_eq!(parse_type("func"), function(vec![], Type::void()));
+        assert_eq!(parse_type("func(func(int8): int8): func"), function(vec![function(vec![Type::named("int8")], Type::named("int8"))], function(vec![], Type::void())));
+        assert_eq!(parse_type("func(a: int32, b: int32) -> int32").to_string(), "func(int32, int32): int32");
+    }
+
+    #[test]
+    fn pointer_and_reference_types_nest() {
+        assert_eq!(parse_type("*int32"), Type::Pointer(Box::new(Type::named("int32"))));
+        assert_eq!(parse_type("&Array<*char>"), Type::Reference(Box::new(Type::Array(Box::new(Type::Pointer(Box::new(Type::named("char"))))))));
+        assert_eq!(parse_type("**func(&string)").to_string(), "**func(&string): void");
+    }
+
+    #[test]
+    fn struct_fields_may_hold_functions() {
+        let (ast, diagnostics) = parse("S: struct {\n    pub fptr: func(s: string): void;\n    f: func -> int32;\n}");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let AST::StructDefinition { fields, methods, .. } = &child[0] else { panic!("expected a struct") };
+        let fields = fields.iter().map(|field| format!("{}: {}", field.name, field.field_type)).collect::<Vec<_>>();
+        assert_eq!(fields, vec!["fptr: func(string): void", "f: func(): int32"]);
+        assert!(methods.is_empty());
+    }
+
+    #[test]
+    fn generic_types_nest_and_take_several_arguments() {
+        let int32 = || Type::named("int32");
+        let array = |element: Type| Type::Array(Box::new(element));
+        assert_eq!(parse_type("Array<Array<int32>>"), array(array(int32())));
+        assert_eq!(parse_type("Array<Array<Array<int32>>>"), array(array(array(int32()))));
+        assert_eq!(parse_type("Map<string, Array<int32>>"), Type::Generic { name: Symbol::intern("Map"), args: vec![Type::named("string"), array(int32())] });
+        assert_eq!(parse_type("Map<Map<K, V>, Array<Array<func(Set<int32>)>>>").to_string(), "Map<Map<K, V>, Array<Array<func(Set<int32>): void>>>");
+    }
+
+    #[test]
+    fn closing_angles_split_off_the_rest_of_the_token() {
+        assert_eq!(statements("f: func { val x: Array<Array<int32>>= y; }"), vec!["VariableDeclaration"]);
+        assert_eq!(statements("f: func { val x: Array<int32>= y; }"), vec!["VariableDeclaration"]);
+        assert_eq!(statements("f: func { var x: Map<int32, Array<int32>>= y; x >>= 1; }"), vec!["VariableDeclaration", "Assign"]);
+        assert_eq!(expression("a >> b"), "(a >> b)");
+    }
+
+    #[test]
+    fn malformed_type_arguments() {
+        assert_eq!(codes("f: func(x: Array<int32, string>) { }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func(x: Map<int32 string>) { }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func(x: Map<>) { }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func(x: Map<int32) { }"), vec![ErrorCode::UnexpectedToken]);
+    }
+
+    #[test]
+    fn signatures_have_one_grammar() {
+        let void = || (Vec::<String>::new(), "void".to_string());
+        assert_eq!(signatures("f: func -> void { }\ng: func { }\nh: func() { }"), vec![void(), void(), void()]);
+        assert_eq!(signatures("f: func: int32 { return 1; }\ng: func -> int32 { return 1; }"), vec![(vec![], "int32".to_string()); 2]);
+        assert_eq!(
+            signatures("f: func(x: int32) { }\ng: func(x: int32, s: string) -> Array<string> { return a; }"),
+            vec![(vec!["int32".to_string()], "void".to_string()), (vec!["int32".to_string(), "string".to_string()], "Array<string>".to_string())]
+        );
+    }
+
+    #[test]
+    fn methods_share_the_function_signature_grammar() {
+        let (ast, diagnostics) = parse("S: struct {\n    pub construct() { }\n    a: func { }\n    b: func -> int32 { return 1; }\n    c: func(x: int32) { }\n}");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let AST::File { child, .. } = ast else { panic!("expected a file") };
+        let AST::StructDefinition { constructors, methods, .. } = &child[0] else { panic!("expected a struct") };
+        assert_eq!(constructors.len(), 1);
+        let returns = methods.iter().map(|method| match &method.function {
+            AST::FunctionDefinition { return_type, .. } => return_type.to_string(),
+            function => panic!("unexpected method {:?}", function)
+        }).collect::<Vec<_>>();
+        assert_eq!(returns, vec!["void", "int32", "void"]);
+    }
+
+    #[test]
+    fn malformed_signatures() {
+        assert_eq!(codes("f: func -> { }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func() int32 { }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func(x: int32 { }"), vec![ErrorCode::UnexpectedToken]);
+        assert_eq!(codes("f: func -> int32 { return; }"), vec![ErrorCode::MissingReturnValue]);
+        assert_eq!(codes("f: func { return; }\ng: func -> void { return; }"), vec![]);
+    }
+}
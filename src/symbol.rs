@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+// an interned string, comparing two symbols is an integer compare
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// names the compiler refers to itself, interned up front in the order of the
+// constants below so that comparing against them needs no lookup
+const PREDEFINED: [&str; 22] = [
+    "", "self", "construct", "Array", "void", "bool", "string", "char",
+    "int", "int8", "int16", "int32", "int64", "uint8", "uint16", "uint32", "uint64",
+    "float32", "float64", "{integer}", "{float}", "{never}"
+];
+
+// interned strings live for the rest of the program, names are never freed
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| {
+        let mut interner = Interner::default();
+        for (index, string) in PREDEFINED.into_iter().enumerate() {
+            interner.symbols.insert(string, Symbol(index as u32));
+            interner.strings.push(string);
+        }
+        Mutex::new(interner)
+    })
+}
+
+impl Symbol {
+    // the value of tokens that aren't names
+    pub const EMPTY: Symbol = Symbol(0);
+    pub const SELF: Symbol = Symbol(1);
+    pub const CONSTRUCT: Symbol = Symbol(2);
+    pub const ARRAY: Symbol = Symbol(3);
+    pub const VOID: Symbol = Symbol(4);
+    pub const BOOL: Symbol = Symbol(5);
+    pub const STRING: Symbol = Symbol(6);
+    pub const CHAR: Symbol = Symbol(7);
+    pub const INT: Symbol = Symbol(8);
+    pub const INT8: Symbol = Symbol(9);
+    pub const INT16: Symbol = Symbol(10);
+    pub const INT32: Symbol = Symbol(11);
+    pub const INT64: Symbol = Symbol(12);
+    pub const UINT8: Symbol = Symbol(13);
+    pub const UINT16: Symbol = Symbol(14);
+    pub const UINT32: Symbol = Symbol(15);
+    pub const UINT64: Symbol = Symbol(16);
+    pub const FLOAT32: Symbol = Symbol(17);
+    pub const FLOAT64: Symbol = Symbol(18);
+    // the types of integer and float literals without a suffix
+    pub const INTEGER: Symbol = Symbol(19);
+    pub const FLOAT: Symbol = Symbol(20);
+    // the type of expressions that never produce a value, like `return`
+    pub const NEVER: Symbol = Symbol(21);
+
+    pub fn intern(string: &str) -> Symbol {
+        let mut interner = interner().lock().unwrap();
+        if let Some(symbol) = interner.symbols.get(string) {
+            return *symbol;
+        }
+
+        let string: &'static str = Box::leak(string.to_string().into_boxed_str());
+        let symbol = Symbol(interner.strings.len() as u32);
+        interner.strings.push(string);
+        interner.symbols.insert(string, symbol);
+        symbol
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        interner().lock().unwrap().strings[self.0 as usize]
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning_is_idempotent() {
+        let symbol = Symbol::intern("interning_is_idempotent");
+        assert_eq!(Symbol::intern("interning_is_idempotent"), symbol);
+        assert_ne!(Symbol::intern("interning_is_idempotent2"), symbol);
+        assert_eq!(symbol.as_str(), "interning_is_idempotent");
+    }
+
+    #[test]
+    fn predefined_symbols_match_their_names() {
+        let predefined = [
+            Symbol::EMPTY, Symbol::SELF, Symbol::CONSTRUCT, Symbol::ARRAY, Symbol::VOID, Symbol::BOOL, Symbol::STRING, Symbol::CHAR,
+            Symbol::INT, Symbol::INT8, Symbol::INT16, Symbol::INT32, Symbol::INT64, Symbol::UINT8, Symbol::UINT16, Symbol::UINT32, Symbol::UINT64,
+            Symbol::FLOAT32, Symbol::FLOAT64, Symbol::INTEGER, Symbol::FLOAT, Symbol::NEVER
+        ];
+        for (symbol, string) in predefined.into_iter().zip(PREDEFINED) {
+            assert_eq!(symbol.as_str(), string);
+            assert_eq!(Symbol::intern(string), symbol);
+        }
+    }
+}